@@ -7,6 +7,23 @@ mod m20250901_142239_create_rules;
 mod m20250903_143112_create_account_rule;
 mod m20250903_152750_create_budget;
 mod m20250919_160321_create_settings;
+mod m20261018_090000_add_csv_settings;
+mod m20261018_110000_add_transaction_external_id;
+mod m20261018_130000_create_import_batches;
+mod m20261018_150000_add_import_batch_history;
+mod m20261018_170000_add_header_mapping;
+mod m20261018_190000_add_amount_layout;
+mod m20261018_210000_create_import_profiles;
+mod m20261018_230000_add_auto_apply_rules;
+mod m20261019_090000_add_row_filters;
+mod m20261019_110000_add_pdf_line_patterns;
+mod m20261019_130000_create_import_files;
+mod m20261019_150000_add_verified_balance;
+mod m20261019_170000_add_rule_amount_conditions;
+mod m20261019_190000_add_rule_conditions;
+mod m20261019_210000_add_rule_priority;
+mod m20261019_230000_rule_patterns_list;
+mod m20261020_090000_add_import_batch_verified_balance;
 
 pub struct Migrator;
 
@@ -21,6 +38,23 @@ impl MigratorTrait for Migrator {
             Box::new(m20250903_143112_create_account_rule::Migration),
             Box::new(m20250903_152750_create_budget::Migration),
             Box::new(m20250919_160321_create_settings::Migration),
+            Box::new(m20261018_090000_add_csv_settings::Migration),
+            Box::new(m20261018_110000_add_transaction_external_id::Migration),
+            Box::new(m20261018_130000_create_import_batches::Migration),
+            Box::new(m20261018_150000_add_import_batch_history::Migration),
+            Box::new(m20261018_170000_add_header_mapping::Migration),
+            Box::new(m20261018_190000_add_amount_layout::Migration),
+            Box::new(m20261018_210000_create_import_profiles::Migration),
+            Box::new(m20261018_230000_add_auto_apply_rules::Migration),
+            Box::new(m20261019_090000_add_row_filters::Migration),
+            Box::new(m20261019_110000_add_pdf_line_patterns::Migration),
+            Box::new(m20261019_130000_create_import_files::Migration),
+            Box::new(m20261019_150000_add_verified_balance::Migration),
+            Box::new(m20261019_170000_add_rule_amount_conditions::Migration),
+            Box::new(m20261019_190000_add_rule_conditions::Migration),
+            Box::new(m20261019_210000_add_rule_priority::Migration),
+            Box::new(m20261019_230000_rule_patterns_list::Migration),
+            Box::new(m20261020_090000_add_import_batch_verified_balance::Migration),
         ]
     }
 }
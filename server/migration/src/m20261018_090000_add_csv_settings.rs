@@ -0,0 +1,80 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Settings::Table)
+                    .add_column(
+                        ColumnDef::new(Settings::Delimiter)
+                            .string()
+                            .not_null()
+                            .default(";"),
+                    )
+                    .add_column(
+                        ColumnDef::new(Settings::QuoteChar)
+                            .string()
+                            .not_null()
+                            .default("\""),
+                    )
+                    .add_column(
+                        ColumnDef::new(Settings::Encoding)
+                            .string()
+                            .not_null()
+                            .default("utf-8"),
+                    )
+                    .add_column(
+                        ColumnDef::new(Settings::DecimalSeparator)
+                            .string()
+                            .not_null()
+                            .default(","),
+                    )
+                    .add_column(
+                        ColumnDef::new(Settings::ThousandsSeparator)
+                            .string()
+                            .not_null()
+                            .default("."),
+                    )
+                    .add_column(
+                        ColumnDef::new(Settings::DateFormat)
+                            .string()
+                            .not_null()
+                            .default("%d/%m/%Y"),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Settings::Table)
+                    .drop_column(Settings::Delimiter)
+                    .drop_column(Settings::QuoteChar)
+                    .drop_column(Settings::Encoding)
+                    .drop_column(Settings::DecimalSeparator)
+                    .drop_column(Settings::ThousandsSeparator)
+                    .drop_column(Settings::DateFormat)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Settings {
+    Table,
+    Delimiter,
+    QuoteChar,
+    Encoding,
+    DecimalSeparator,
+    ThousandsSeparator,
+    DateFormat,
+}
@@ -0,0 +1,126 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImportBatches::Table)
+                    .if_not_exists()
+                    .col(pk_auto(ImportBatches::Id))
+                    .col(
+                        ColumnDef::new(ImportBatches::AccountId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ImportBatches::Filename).string().not_null())
+                    .col(ColumnDef::new(ImportBatches::Status).string().not_null())
+                    .col(
+                        ColumnDef::new(ImportBatches::CreatedAt)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_import_batches_account")
+                            .from(ImportBatches::Table, ImportBatches::AccountId)
+                            .to(Accounts::Table, Accounts::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImportRows::Table)
+                    .if_not_exists()
+                    .col(pk_auto(ImportRows::Id))
+                    .col(ColumnDef::new(ImportRows::BatchId).integer().not_null())
+                    .col(ColumnDef::new(ImportRows::Position).integer().not_null())
+                    .col(ColumnDef::new(ImportRows::Date).date().not_null())
+                    .col(ColumnDef::new(ImportRows::Description).string().not_null())
+                    .col(ColumnDef::new(ImportRows::Value).double().not_null())
+                    .col(ColumnDef::new(ImportRows::Label).string().not_null())
+                    .col(ColumnDef::new(ImportRows::CategoryId).integer().null())
+                    .col(ColumnDef::new(ImportRows::ExternalId).string().null())
+                    .col(
+                        ColumnDef::new(ImportRows::DuplicateStatus)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ImportRows::DuplicateOf).integer().null())
+                    .col(ColumnDef::new(ImportRows::Dropped).boolean().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_import_rows_batch")
+                            .from(ImportRows::Table, ImportRows::BatchId)
+                            .to(ImportBatches::Table, ImportBatches::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_import_rows_category")
+                            .from(ImportRows::Table, ImportRows::CategoryId)
+                            .to(Categories::Table, Categories::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ImportRows::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(ImportBatches::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum Accounts {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum Categories {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum ImportBatches {
+    Table,
+    Id,
+    AccountId,
+    Filename,
+    Status,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum ImportRows {
+    Table,
+    Id,
+    BatchId,
+    Position,
+    Date,
+    Description,
+    Value,
+    Label,
+    CategoryId,
+    ExternalId,
+    DuplicateStatus,
+    DuplicateOf,
+    Dropped,
+}
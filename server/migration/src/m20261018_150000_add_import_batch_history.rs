@@ -0,0 +1,106 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ImportBatches::Table)
+                    .add_column(
+                        ColumnDef::new(ImportBatches::FileHash)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(
+                        ColumnDef::new(ImportBatches::RowsTotal)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .add_column(
+                        ColumnDef::new(ImportBatches::RowsImported)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .add_column(
+                        ColumnDef::new(ImportBatches::DuplicatesSkipped)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .add_column(
+                        ColumnDef::new(ImportBatches::CommittedAt)
+                            .timestamp()
+                            .null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Transactions::Table)
+                    .add_column(ColumnDef::new(Transactions::ImportBatchId).integer().null())
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk_transactions_import_batch")
+                            .from_tbl(Transactions::Table)
+                            .from_col(Transactions::ImportBatchId)
+                            .to_tbl(ImportBatches::Table)
+                            .to_col(ImportBatches::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Transactions::Table)
+                    .drop_foreign_key(Alias::new("fk_transactions_import_batch"))
+                    .drop_column(Transactions::ImportBatchId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ImportBatches::Table)
+                    .drop_column(ImportBatches::FileHash)
+                    .drop_column(ImportBatches::RowsTotal)
+                    .drop_column(ImportBatches::RowsImported)
+                    .drop_column(ImportBatches::DuplicatesSkipped)
+                    .drop_column(ImportBatches::CommittedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ImportBatches {
+    Table,
+    Id,
+    FileHash,
+    RowsTotal,
+    RowsImported,
+    DuplicatesSkipped,
+    CommittedAt,
+}
+
+#[derive(DeriveIden)]
+enum Transactions {
+    Table,
+    ImportBatchId,
+}
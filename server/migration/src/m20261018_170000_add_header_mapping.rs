@@ -0,0 +1,129 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Empty header names fall back to the existing column indexes, so
+        // current settings keep importing the same columns.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Settings::Table)
+                    .add_column(
+                        ColumnDef::new(Settings::DateHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(
+                        ColumnDef::new(Settings::DescriptionHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(
+                        ColumnDef::new(Settings::ValueHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(
+                        ColumnDef::new(Settings::CurrencyHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(ColumnDef::new(Settings::CurrencyIndex).integer().null())
+                    .add_column(
+                        ColumnDef::new(Settings::CounterpartyHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(ColumnDef::new(Settings::CounterpartyIndex).integer().null())
+                    .add_column(
+                        ColumnDef::new(Settings::ReferenceHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(ColumnDef::new(Settings::ReferenceIndex).integer().null())
+                    .add_column(
+                        ColumnDef::new(Settings::BalanceHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(ColumnDef::new(Settings::BalanceIndex).integer().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ImportRows::Table)
+                    .add_column(ColumnDef::new(ImportRows::Currency).string().null())
+                    .add_column(ColumnDef::new(ImportRows::Balance).double().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ImportRows::Table)
+                    .drop_column(ImportRows::Currency)
+                    .drop_column(ImportRows::Balance)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Settings::Table)
+                    .drop_column(Settings::DateHeader)
+                    .drop_column(Settings::DescriptionHeader)
+                    .drop_column(Settings::ValueHeader)
+                    .drop_column(Settings::CurrencyHeader)
+                    .drop_column(Settings::CurrencyIndex)
+                    .drop_column(Settings::CounterpartyHeader)
+                    .drop_column(Settings::CounterpartyIndex)
+                    .drop_column(Settings::ReferenceHeader)
+                    .drop_column(Settings::ReferenceIndex)
+                    .drop_column(Settings::BalanceHeader)
+                    .drop_column(Settings::BalanceIndex)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Settings {
+    Table,
+    DateHeader,
+    DescriptionHeader,
+    ValueHeader,
+    CurrencyHeader,
+    CurrencyIndex,
+    CounterpartyHeader,
+    CounterpartyIndex,
+    ReferenceHeader,
+    ReferenceIndex,
+    BalanceHeader,
+    BalanceIndex,
+}
+
+#[derive(DeriveIden)]
+enum ImportRows {
+    Table,
+    Currency,
+    Balance,
+}
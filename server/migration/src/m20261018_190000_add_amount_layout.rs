@@ -0,0 +1,89 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Settings::Table)
+                    .add_column(
+                        ColumnDef::new(Settings::AmountLayout)
+                            .string()
+                            .not_null()
+                            .default("signed"),
+                    )
+                    .add_column(
+                        ColumnDef::new(Settings::DebitHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(ColumnDef::new(Settings::DebitIndex).integer().null())
+                    .add_column(
+                        ColumnDef::new(Settings::CreditHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(ColumnDef::new(Settings::CreditIndex).integer().null())
+                    .add_column(
+                        ColumnDef::new(Settings::DirectionHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(ColumnDef::new(Settings::DirectionIndex).integer().null())
+                    .add_column(
+                        ColumnDef::new(Settings::DebitMarkers)
+                            .string()
+                            .not_null()
+                            .default("D|Dare|Addebito|Uscita"),
+                    )
+                    .add_column(
+                        ColumnDef::new(Settings::InvertSign)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Settings::Table)
+                    .drop_column(Settings::AmountLayout)
+                    .drop_column(Settings::DebitHeader)
+                    .drop_column(Settings::DebitIndex)
+                    .drop_column(Settings::CreditHeader)
+                    .drop_column(Settings::CreditIndex)
+                    .drop_column(Settings::DirectionHeader)
+                    .drop_column(Settings::DirectionIndex)
+                    .drop_column(Settings::DebitMarkers)
+                    .drop_column(Settings::InvertSign)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Settings {
+    Table,
+    AmountLayout,
+    DebitHeader,
+    DebitIndex,
+    CreditHeader,
+    CreditIndex,
+    DirectionHeader,
+    DirectionIndex,
+    DebitMarkers,
+    InvertSign,
+}
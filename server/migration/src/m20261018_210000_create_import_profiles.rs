@@ -0,0 +1,551 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImportProfiles::Table)
+                    .if_not_exists()
+                    .col(pk_auto(ImportProfiles::Id))
+                    .col(ColumnDef::new(ImportProfiles::Name).string().not_null())
+                    .col(
+                        ColumnDef::new(ImportProfiles::Builtin)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::Format)
+                            .string()
+                            .not_null()
+                            .default("auto"),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::StarterString)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::DateIndex)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::DescriptionIndex)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::ValueIndex)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::Delimiter)
+                            .string()
+                            .not_null()
+                            .default(";"),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::QuoteChar)
+                            .string()
+                            .not_null()
+                            .default("\""),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::Encoding)
+                            .string()
+                            .not_null()
+                            .default("utf-8"),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::DecimalSeparator)
+                            .string()
+                            .not_null()
+                            .default(","),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::ThousandsSeparator)
+                            .string()
+                            .not_null()
+                            .default("."),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::DateFormat)
+                            .string()
+                            .not_null()
+                            .default("%d/%m/%Y"),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::DateHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::DescriptionHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::ValueHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::CurrencyHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::CurrencyIndex)
+                            .integer()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::CounterpartyHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::CounterpartyIndex)
+                            .integer()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::ReferenceHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::ReferenceIndex)
+                            .integer()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::BalanceHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::BalanceIndex)
+                            .integer()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::AmountLayout)
+                            .string()
+                            .not_null()
+                            .default("signed"),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::DebitHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .col(ColumnDef::new(ImportProfiles::DebitIndex).integer().null())
+                    .col(
+                        ColumnDef::new(ImportProfiles::CreditHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .col(ColumnDef::new(ImportProfiles::CreditIndex).integer().null())
+                    .col(
+                        ColumnDef::new(ImportProfiles::DirectionHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::DirectionIndex)
+                            .integer()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::DebitMarkers)
+                            .string()
+                            .not_null()
+                            .default("D|Dare|Addebito|Uscita"),
+                    )
+                    .col(
+                        ColumnDef::new(ImportProfiles::InvertSign)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Settings::Table)
+                    .add_column(ColumnDef::new(Settings::ProfileId).integer().null())
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk_settings_import_profile")
+                            .from_tbl(Settings::Table)
+                            .from_col(Settings::ProfileId)
+                            .to_tbl(ImportProfiles::Table)
+                            .to_col(ImportProfiles::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Every account keeps its current mapping as a profile of its own,
+        // reusing the settings id so the two can be linked back.
+        let db = manager.get_connection();
+        db.execute_unprepared(
+            "INSERT INTO import_profiles (id, name, builtin, format, starter_string, \
+             date_index, description_index, value_index, delimiter, quote_char, encoding, \
+             decimal_separator, thousands_separator, date_format, date_header, \
+             description_header, value_header, currency_header, currency_index, \
+             counterparty_header, counterparty_index, reference_header, reference_index, \
+             balance_header, balance_index, amount_layout, debit_header, debit_index, \
+             credit_header, credit_index, direction_header, direction_index, debit_markers, \
+             invert_sign) \
+             SELECT s.id, a.name, false, 'auto', s.starter_string, s.date_index, \
+             s.description_index, s.value_index, s.delimiter, s.quote_char, s.encoding, \
+             s.decimal_separator, s.thousands_separator, s.date_format, s.date_header, \
+             s.description_header, s.value_header, s.currency_header, s.currency_index, \
+             s.counterparty_header, s.counterparty_index, s.reference_header, \
+             s.reference_index, s.balance_header, s.balance_index, s.amount_layout, \
+             s.debit_header, s.debit_index, s.credit_header, s.credit_index, \
+             s.direction_header, s.direction_index, s.debit_markers, s.invert_sign \
+             FROM settings s JOIN accounts a ON a.id = s.account_id",
+        )
+        .await?;
+        db.execute_unprepared("UPDATE settings SET profile_id = id")
+            .await?;
+        db.execute_unprepared(
+            "SELECT setval('import_profiles_id_seq', COALESCE(MAX(id), 1), MAX(id) IS NOT NULL) \
+             FROM import_profiles",
+        )
+        .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Settings::Table)
+                    .drop_column(Settings::StarterString)
+                    .drop_column(Settings::DateIndex)
+                    .drop_column(Settings::DescriptionIndex)
+                    .drop_column(Settings::ValueIndex)
+                    .drop_column(Settings::Delimiter)
+                    .drop_column(Settings::QuoteChar)
+                    .drop_column(Settings::Encoding)
+                    .drop_column(Settings::DecimalSeparator)
+                    .drop_column(Settings::ThousandsSeparator)
+                    .drop_column(Settings::DateFormat)
+                    .drop_column(Settings::DateHeader)
+                    .drop_column(Settings::DescriptionHeader)
+                    .drop_column(Settings::ValueHeader)
+                    .drop_column(Settings::CurrencyHeader)
+                    .drop_column(Settings::CurrencyIndex)
+                    .drop_column(Settings::CounterpartyHeader)
+                    .drop_column(Settings::CounterpartyIndex)
+                    .drop_column(Settings::ReferenceHeader)
+                    .drop_column(Settings::ReferenceIndex)
+                    .drop_column(Settings::BalanceHeader)
+                    .drop_column(Settings::BalanceIndex)
+                    .drop_column(Settings::AmountLayout)
+                    .drop_column(Settings::DebitHeader)
+                    .drop_column(Settings::DebitIndex)
+                    .drop_column(Settings::CreditHeader)
+                    .drop_column(Settings::CreditIndex)
+                    .drop_column(Settings::DirectionHeader)
+                    .drop_column(Settings::DirectionIndex)
+                    .drop_column(Settings::DebitMarkers)
+                    .drop_column(Settings::InvertSign)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Settings::Table)
+                    .add_column(
+                        ColumnDef::new(Settings::StarterString)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(
+                        ColumnDef::new(Settings::DateIndex)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .add_column(
+                        ColumnDef::new(Settings::DescriptionIndex)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .add_column(
+                        ColumnDef::new(Settings::ValueIndex)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .add_column(
+                        ColumnDef::new(Settings::Delimiter)
+                            .string()
+                            .not_null()
+                            .default(";"),
+                    )
+                    .add_column(
+                        ColumnDef::new(Settings::QuoteChar)
+                            .string()
+                            .not_null()
+                            .default("\""),
+                    )
+                    .add_column(
+                        ColumnDef::new(Settings::Encoding)
+                            .string()
+                            .not_null()
+                            .default("utf-8"),
+                    )
+                    .add_column(
+                        ColumnDef::new(Settings::DecimalSeparator)
+                            .string()
+                            .not_null()
+                            .default(","),
+                    )
+                    .add_column(
+                        ColumnDef::new(Settings::ThousandsSeparator)
+                            .string()
+                            .not_null()
+                            .default("."),
+                    )
+                    .add_column(
+                        ColumnDef::new(Settings::DateFormat)
+                            .string()
+                            .not_null()
+                            .default("%d/%m/%Y"),
+                    )
+                    .add_column(
+                        ColumnDef::new(Settings::DateHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(
+                        ColumnDef::new(Settings::DescriptionHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(
+                        ColumnDef::new(Settings::ValueHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(
+                        ColumnDef::new(Settings::CurrencyHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(ColumnDef::new(Settings::CurrencyIndex).integer().null())
+                    .add_column(
+                        ColumnDef::new(Settings::CounterpartyHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(ColumnDef::new(Settings::CounterpartyIndex).integer().null())
+                    .add_column(
+                        ColumnDef::new(Settings::ReferenceHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(ColumnDef::new(Settings::ReferenceIndex).integer().null())
+                    .add_column(
+                        ColumnDef::new(Settings::BalanceHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(ColumnDef::new(Settings::BalanceIndex).integer().null())
+                    .add_column(
+                        ColumnDef::new(Settings::AmountLayout)
+                            .string()
+                            .not_null()
+                            .default("signed"),
+                    )
+                    .add_column(
+                        ColumnDef::new(Settings::DebitHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(ColumnDef::new(Settings::DebitIndex).integer().null())
+                    .add_column(
+                        ColumnDef::new(Settings::CreditHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(ColumnDef::new(Settings::CreditIndex).integer().null())
+                    .add_column(
+                        ColumnDef::new(Settings::DirectionHeader)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(ColumnDef::new(Settings::DirectionIndex).integer().null())
+                    .add_column(
+                        ColumnDef::new(Settings::DebitMarkers)
+                            .string()
+                            .not_null()
+                            .default("D|Dare|Addebito|Uscita"),
+                    )
+                    .add_column(
+                        ColumnDef::new(Settings::InvertSign)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "UPDATE settings s SET starter_string = p.starter_string, date_index = \
+                 p.date_index, description_index = p.description_index, value_index = \
+                 p.value_index, delimiter = p.delimiter, quote_char = p.quote_char, \
+                 encoding = p.encoding, decimal_separator = p.decimal_separator, \
+                 thousands_separator = p.thousands_separator, date_format = p.date_format, \
+                 date_header = p.date_header, description_header = p.description_header, \
+                 value_header = p.value_header, currency_header = p.currency_header, \
+                 currency_index = p.currency_index, counterparty_header = \
+                 p.counterparty_header, counterparty_index = p.counterparty_index, \
+                 reference_header = p.reference_header, reference_index = \
+                 p.reference_index, balance_header = p.balance_header, balance_index = \
+                 p.balance_index, amount_layout = p.amount_layout, debit_header = \
+                 p.debit_header, debit_index = p.debit_index, credit_header = \
+                 p.credit_header, credit_index = p.credit_index, direction_header = \
+                 p.direction_header, direction_index = p.direction_index, debit_markers = \
+                 p.debit_markers, invert_sign = p.invert_sign \
+                 FROM import_profiles p WHERE p.id = s.profile_id",
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Settings::Table)
+                    .drop_foreign_key(Alias::new("fk_settings_import_profile"))
+                    .drop_column(Settings::ProfileId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(ImportProfiles::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ImportProfiles {
+    Table,
+    Id,
+    Name,
+    Builtin,
+    Format,
+    StarterString,
+    DateIndex,
+    DescriptionIndex,
+    ValueIndex,
+    Delimiter,
+    QuoteChar,
+    Encoding,
+    DecimalSeparator,
+    ThousandsSeparator,
+    DateFormat,
+    DateHeader,
+    DescriptionHeader,
+    ValueHeader,
+    CurrencyHeader,
+    CurrencyIndex,
+    CounterpartyHeader,
+    CounterpartyIndex,
+    ReferenceHeader,
+    ReferenceIndex,
+    BalanceHeader,
+    BalanceIndex,
+    AmountLayout,
+    DebitHeader,
+    DebitIndex,
+    CreditHeader,
+    CreditIndex,
+    DirectionHeader,
+    DirectionIndex,
+    DebitMarkers,
+    InvertSign,
+}
+
+#[derive(DeriveIden)]
+enum Settings {
+    Table,
+    ProfileId,
+    StarterString,
+    DateIndex,
+    DescriptionIndex,
+    ValueIndex,
+    Delimiter,
+    QuoteChar,
+    Encoding,
+    DecimalSeparator,
+    ThousandsSeparator,
+    DateFormat,
+    DateHeader,
+    DescriptionHeader,
+    ValueHeader,
+    CurrencyHeader,
+    CurrencyIndex,
+    CounterpartyHeader,
+    CounterpartyIndex,
+    ReferenceHeader,
+    ReferenceIndex,
+    BalanceHeader,
+    BalanceIndex,
+    AmountLayout,
+    DebitHeader,
+    DebitIndex,
+    CreditHeader,
+    CreditIndex,
+    DirectionHeader,
+    DirectionIndex,
+    DebitMarkers,
+    InvertSign,
+}
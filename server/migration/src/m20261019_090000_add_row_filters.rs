@@ -0,0 +1,64 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ImportProfiles::Table)
+                    .add_column(
+                        ColumnDef::new(ImportProfiles::Worksheet)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(
+                        ColumnDef::new(ImportProfiles::EndMarker)
+                            .string()
+                            .not_null()
+                            .default(""),
+                    )
+                    .add_column(
+                        ColumnDef::new(ImportProfiles::StopAtBlankRow)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .add_column(
+                        ColumnDef::new(ImportProfiles::SkipPatterns)
+                            .text()
+                            .not_null()
+                            .default(""),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ImportProfiles::Table)
+                    .drop_column(ImportProfiles::Worksheet)
+                    .drop_column(ImportProfiles::EndMarker)
+                    .drop_column(ImportProfiles::StopAtBlankRow)
+                    .drop_column(ImportProfiles::SkipPatterns)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ImportProfiles {
+    Table,
+    Worksheet,
+    EndMarker,
+    StopAtBlankRow,
+    SkipPatterns,
+}
@@ -0,0 +1,54 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImportFiles::Table)
+                    .if_not_exists()
+                    .col(pk_auto(ImportFiles::Id))
+                    .col(ColumnDef::new(ImportFiles::BatchId).integer().not_null())
+                    .col(ColumnDef::new(ImportFiles::Position).integer().not_null())
+                    .col(ColumnDef::new(ImportFiles::Filename).string().not_null())
+                    .col(ColumnDef::new(ImportFiles::FileHash).string().not_null())
+                    .col(ColumnDef::new(ImportFiles::ProfileSnapshot).text().null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_import_files_batch")
+                            .from(ImportFiles::Table, ImportFiles::BatchId)
+                            .to(ImportBatches::Table, ImportBatches::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ImportFiles::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ImportBatches {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum ImportFiles {
+    Table,
+    Id,
+    BatchId,
+    Position,
+    Filename,
+    FileHash,
+    ProfileSnapshot,
+}
@@ -0,0 +1,48 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Existing rules get no amount condition and keep matching as before.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Rules::Table)
+                    .add_column(ColumnDef::new(Rules::AmountMin).double().null())
+                    .add_column(ColumnDef::new(Rules::AmountMax).double().null())
+                    .add_column(ColumnDef::new(Rules::AmountExact).double().null())
+                    .add_column(ColumnDef::new(Rules::AmountTolerance).double().null())
+                    .add_column(ColumnDef::new(Rules::AmountSign).string().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Rules::Table)
+                    .drop_column(Rules::AmountMin)
+                    .drop_column(Rules::AmountMax)
+                    .drop_column(Rules::AmountExact)
+                    .drop_column(Rules::AmountTolerance)
+                    .drop_column(Rules::AmountSign)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Rules {
+    Table,
+    AmountMin,
+    AmountMax,
+    AmountExact,
+    AmountTolerance,
+    AmountSign,
+}
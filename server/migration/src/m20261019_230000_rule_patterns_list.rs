@@ -0,0 +1,87 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Rules::Table)
+                    .add_column(
+                        ColumnDef::new(Rules::Patterns)
+                            .json_binary()
+                            .not_null()
+                            .default(Expr::cust("'[]'::jsonb")),
+                    )
+                    .add_column(
+                        ColumnDef::new(Rules::PatternsCaseInsensitive)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // The comma separated patterns become one list entry each, empty
+        // fragments matched everything and are dropped.
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "UPDATE rules SET patterns = \
+                 to_jsonb(array_remove(string_to_array(regexpr, ','), '')) \
+                 WHERE regexpr IS NOT NULL",
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Rules::Table)
+                    .drop_column(Rules::Regexpr)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Rules::Table)
+                    .add_column(ColumnDef::new(Rules::Regexpr).string().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "UPDATE rules SET regexpr = \
+                 array_to_string(ARRAY(SELECT jsonb_array_elements_text(patterns)), ',') \
+                 WHERE jsonb_array_length(patterns) > 0",
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Rules::Table)
+                    .drop_column(Rules::Patterns)
+                    .drop_column(Rules::PatternsCaseInsensitive)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Rules {
+    Table,
+    Regexpr,
+    Patterns,
+    PatternsCaseInsensitive,
+}
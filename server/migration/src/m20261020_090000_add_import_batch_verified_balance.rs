@@ -0,0 +1,60 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ImportBatches::Table)
+                    .add_column(
+                        ColumnDef::new(ImportBatches::VerifiedBalance)
+                            .double()
+                            .null(),
+                    )
+                    .add_column(
+                        ColumnDef::new(ImportBatches::VerifiedBalanceDate)
+                            .date()
+                            .null(),
+                    )
+                    .add_column(
+                        ColumnDef::new(ImportBatches::PreviousVerifiedBalance)
+                            .double()
+                            .null(),
+                    )
+                    .add_column(
+                        ColumnDef::new(ImportBatches::PreviousVerifiedBalanceDate)
+                            .date()
+                            .null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ImportBatches::Table)
+                    .drop_column(ImportBatches::VerifiedBalance)
+                    .drop_column(ImportBatches::VerifiedBalanceDate)
+                    .drop_column(ImportBatches::PreviousVerifiedBalance)
+                    .drop_column(ImportBatches::PreviousVerifiedBalanceDate)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ImportBatches {
+    Table,
+    VerifiedBalance,
+    VerifiedBalanceDate,
+    PreviousVerifiedBalance,
+    PreviousVerifiedBalanceDate,
+}
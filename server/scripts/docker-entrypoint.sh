@@ -9,8 +9,10 @@ EXISTS=$(psql "$DATABASE_URL" -tAc "SELECT 1 FROM information_schema.tables WHER
 
 if [ "$EXISTS" != "1" ]; then
   echo "⚡ Empty DB, launching migration..."
-  /app/migration up
+else
+  echo "⚡ Applying pending migrations..."
 fi
+/app/migration up
 
 echo "▶️ Starting server..."
 exec /app/server
@@ -18,6 +18,8 @@ pub enum Relation {
     Budgets,
     #[sea_orm(has_one = "super::settings::Entity")]
     Settings,
+    #[sea_orm(has_many = "super::import_batch::Entity")]
+    ImportBatches,
 }
 
 impl ActiveModelBehavior for ActiveModel {}
@@ -49,3 +51,9 @@ impl Related<super::settings::Entity> for Entity {
         Relation::Settings.def()
     }
 }
+
+impl Related<super::import_batch::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ImportBatches.def()
+    }
+}
@@ -0,0 +1,71 @@
+use sea_orm::entity::prelude::*;
+
+use crate::database::account;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "import_batches")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub account_id: i32,
+    pub filename: String,
+    pub status: String,
+    pub created_at: DateTime,
+    pub file_hash: String,
+    pub rows_total: i32,
+    pub rows_imported: i32,
+    pub duplicates_skipped: i32,
+    pub committed_at: Option<DateTime>,
+    /// Verified balance of the account set by the commit of this batch, and
+    /// the one it replaced, put back when the batch is rolled back.
+    pub verified_balance: Option<f64>,
+    pub verified_balance_date: Option<Date>,
+    pub previous_verified_balance: Option<f64>,
+    pub previous_verified_balance_date: Option<Date>,
+}
+
+pub const STATUS_STAGED: &str = "staged";
+pub const STATUS_COMMITTED: &str = "committed";
+pub const STATUS_ROLLED_BACK: &str = "rolled_back";
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::account::Entity",
+        from = "Column::AccountId",
+        to = "super::account::Column::Id"
+    )]
+    Account,
+    #[sea_orm(has_many = "super::import_row::Entity")]
+    ImportRows,
+    #[sea_orm(has_many = "super::import_file::Entity")]
+    ImportFiles,
+    #[sea_orm(has_many = "super::transaction::Entity")]
+    Transactions,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Related<account::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Account.def()
+    }
+}
+
+impl Related<super::import_row::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ImportRows.def()
+    }
+}
+
+impl Related<super::import_file::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ImportFiles.def()
+    }
+}
+
+impl Related<super::transaction::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Transactions.def()
+    }
+}
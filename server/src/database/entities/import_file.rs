@@ -0,0 +1,35 @@
+use sea_orm::entity::prelude::*;
+
+/// A statement file of an import batch. The content is kept by the statement
+/// store under its hash, the profile snapshot is the import profile used to
+/// parse it, as exported to JSON.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "import_files")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub batch_id: i32,
+    pub position: i32,
+    pub filename: String,
+    pub file_hash: String,
+    /// Empty for self-describing formats uploaded without a profile.
+    pub profile_snapshot: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::import_batch::Entity",
+        from = "Column::BatchId",
+        to = "super::import_batch::Column::Id"
+    )]
+    ImportBatch,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Related<super::import_batch::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ImportBatch.def()
+    }
+}
@@ -0,0 +1,82 @@
+use sea_orm::entity::prelude::*;
+
+/// How statements from a bank are read: file format, header detection,
+/// column mapping, date format and sign rules. Accounts at the same bank can
+/// share one profile through their settings.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "import_profiles")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub name: String,
+    /// Shipped with the application, can be duplicated but not edited.
+    pub builtin: bool,
+    pub format: String,
+    pub starter_string: String,
+    pub date_index: i32,
+    pub description_index: i32,
+    pub value_index: i32,
+    pub delimiter: String,
+    pub quote_char: String,
+    pub encoding: String,
+    pub decimal_separator: String,
+    pub thousands_separator: String,
+    pub date_format: String,
+    /// `|` separated header names, tried before the matching `*_index`.
+    pub date_header: String,
+    pub description_header: String,
+    pub value_header: String,
+    pub currency_header: String,
+    pub currency_index: Option<i32>,
+    pub counterparty_header: String,
+    pub counterparty_index: Option<i32>,
+    pub reference_header: String,
+    pub reference_index: Option<i32>,
+    pub balance_header: String,
+    pub balance_index: Option<i32>,
+    pub amount_layout: String,
+    pub debit_header: String,
+    pub debit_index: Option<i32>,
+    pub credit_header: String,
+    pub credit_index: Option<i32>,
+    pub direction_header: String,
+    pub direction_index: Option<i32>,
+    /// `|` separated values of the direction column that mark a debit.
+    pub debit_markers: String,
+    pub invert_sign: bool,
+    /// Sheet of a workbook to read, by name or 0-based index. Empty for the
+    /// first one.
+    pub worksheet: String,
+    /// Text that ends the movements, e.g. the title of a totals section.
+    pub end_marker: String,
+    pub stop_at_blank_row: bool,
+    /// One regular expression per line, rows with a matching cell are skipped.
+    pub skip_patterns: String,
+    /// One regular expression per line for text PDFs, with the named groups
+    /// `date`, `description` and `amount`.
+    pub pdf_line_patterns: String,
+}
+
+pub const FORMAT_AUTO: &str = "auto";
+pub const FORMAT_CSV: &str = "csv";
+pub const FORMAT_XLSX: &str = "xlsx";
+pub const FORMAT_XLS: &str = "xls";
+pub const FORMAT_PDF: &str = "pdf";
+
+pub const AMOUNT_SIGNED: &str = "signed";
+pub const AMOUNT_SPLIT: &str = "split";
+pub const AMOUNT_DIRECTION: &str = "direction";
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(has_many = "super::settings::Entity")]
+    Settings,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Related<super::settings::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Settings.def()
+    }
+}
@@ -0,0 +1,56 @@
+use chrono::NaiveDate;
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "import_rows")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub batch_id: i32,
+    pub position: i32,
+    pub date: NaiveDate,
+    pub description: String,
+    pub value: f64,
+    pub label: String,
+    pub category_id: Option<i32>,
+    pub external_id: Option<String>,
+    pub duplicate_status: String,
+    pub duplicate_of: Option<i32>,
+    pub dropped: bool,
+    pub currency: Option<String>,
+    pub balance: Option<f64>,
+}
+
+pub const UNIQUE: &str = "unique";
+pub const DUPLICATE: &str = "duplicate";
+pub const NEAR_DUPLICATE: &str = "near_duplicate";
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::import_batch::Entity",
+        from = "Column::BatchId",
+        to = "super::import_batch::Column::Id"
+    )]
+    ImportBatch,
+    #[sea_orm(
+        belongs_to = "super::category::Entity",
+        from = "Column::CategoryId",
+        to = "super::category::Column::Id"
+    )]
+    Category,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Related<super::import_batch::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ImportBatch.def()
+    }
+}
+
+impl Related<super::category::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Category.def()
+    }
+}
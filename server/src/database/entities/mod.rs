@@ -2,6 +2,10 @@ pub mod account;
 pub mod account_rule;
 pub mod budget;
 pub mod category;
+pub mod import_batch;
+pub mod import_file;
+pub mod import_profile;
+pub mod import_row;
 pub mod rule;
 pub mod settings;
 pub mod transaction;
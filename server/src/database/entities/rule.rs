@@ -1,5 +1,5 @@
 use chrono::NaiveDate;
-use sea_orm::entity::prelude::*;
+use sea_orm::{entity::prelude::*, FromJsonQueryResult};
 use serde::{Deserialize, Serialize};
 
 use super::category;
@@ -13,11 +13,33 @@ pub struct Model {
     pub label: String,
     pub percentage: f32,
     pub category_id: i32,
-    pub regexpr: Option<String>,
+    /// Regular expressions searched in the description, the rule matches
+    /// when any of them does.
+    #[sea_orm(column_type = "JsonBinary")]
+    pub patterns: Patterns,
+    pub patterns_case_insensitive: bool,
     pub date_start: Option<NaiveDate>,
     pub date_end: Option<NaiveDate>,
+    /// Amount conditions compare the absolute value of the transaction, the
+    /// sign is checked on its own by `amount_sign`.
+    pub amount_min: Option<f64>,
+    pub amount_max: Option<f64>,
+    pub amount_exact: Option<f64>,
+    pub amount_tolerance: Option<f64>,
+    pub amount_sign: Option<String>,
+    /// JSON condition tree, see `routes::rule_conditions::Condition`.
+    pub conditions: Option<String>,
+    /// Among matching rules, the highest priority wins when the account
+    /// conflict policy allows it.
+    pub priority: i32,
 }
 
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, FromJsonQueryResult)]
+pub struct Patterns(pub Vec<String>);
+
+pub const SIGN_INCOME: &str = "income";
+pub const SIGN_EXPENSE: &str = "expense";
+
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(
@@ -8,12 +8,25 @@ pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
     pub account_id: i32,
-    pub date_index: i32,
-    pub description_index: i32,
-    pub value_index: i32,
-    pub starter_string: String,
+    pub profile_id: Option<i32>,
+    /// Runs the account rules on the rows of every committed import.
+    pub auto_apply_rules: bool,
+    /// Closing balance of the last committed import whose running balance
+    /// reconciled, the next statement is expected to start from it.
+    pub verified_balance: Option<f64>,
+    pub verified_balance_date: Option<Date>,
+    /// How a transaction matched by several rules is settled, one of the
+    /// `CONFLICT_*` values.
+    pub rule_conflict_policy: String,
 }
 
+/// The matching rule with the highest priority wins.
+pub const CONFLICT_PRIORITY: &str = "priority";
+/// The matching rule with the most conditions wins, then the highest priority.
+pub const CONFLICT_SPECIFIC: &str = "specific";
+/// Every transaction matched by several rules is left to the user.
+pub const CONFLICT_ASK: &str = "ask";
+
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(
@@ -22,6 +35,12 @@ pub enum Relation {
         to = "super::account::Column::Id"
     )]
     Account,
+    #[sea_orm(
+        belongs_to = "super::import_profile::Entity",
+        from = "Column::ProfileId",
+        to = "super::import_profile::Column::Id"
+    )]
+    ImportProfile,
 }
 
 impl ActiveModelBehavior for ActiveModel {}
@@ -31,3 +50,9 @@ impl Related<account::Entity> for Entity {
         Relation::Account.def()
     }
 }
+
+impl Related<super::import_profile::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ImportProfile.def()
+    }
+}
@@ -13,6 +13,8 @@ pub struct Model {
     pub date: DateTime,
     pub perc_to_exclude: f32,
     pub label: String,
+    pub external_id: Option<String>,
+    pub import_batch_id: Option<i32>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -29,6 +31,12 @@ pub enum Relation {
         to = "super::category::Column::Id"
     )]
     Category,
+    #[sea_orm(
+        belongs_to = "super::import_batch::Entity",
+        from = "Column::ImportBatchId",
+        to = "super::import_batch::Column::Id"
+    )]
+    ImportBatch,
 }
 
 impl ActiveModelBehavior for ActiveModel {}
@@ -44,3 +52,9 @@ impl Related<super::category::Entity> for Entity {
         Relation::Category.def()
     }
 }
+
+impl Related<super::import_batch::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ImportBatch.def()
+    }
+}
@@ -0,0 +1,120 @@
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter,
+};
+
+use crate::database::entities::import_profile;
+
+/// A profile with the same defaults used by the settings migrations:
+/// semicolon separated UTF-8 CSV, Italian number and date format, one signed
+/// amount column.
+pub fn default_profile(name: &str) -> import_profile::ActiveModel {
+    import_profile::ActiveModel {
+        name: Set(name.to_string()),
+        builtin: Set(false),
+        format: Set(import_profile::FORMAT_AUTO.to_string()),
+        starter_string: Set("".to_string()),
+        date_index: Set(0),
+        description_index: Set(0),
+        value_index: Set(0),
+        delimiter: Set(";".to_string()),
+        quote_char: Set("\"".to_string()),
+        encoding: Set("utf-8".to_string()),
+        decimal_separator: Set(",".to_string()),
+        thousands_separator: Set(".".to_string()),
+        date_format: Set("%d/%m/%Y".to_string()),
+        date_header: Set("".to_string()),
+        description_header: Set("".to_string()),
+        value_header: Set("".to_string()),
+        currency_header: Set("".to_string()),
+        currency_index: Set(None),
+        counterparty_header: Set("".to_string()),
+        counterparty_index: Set(None),
+        reference_header: Set("".to_string()),
+        reference_index: Set(None),
+        balance_header: Set("".to_string()),
+        balance_index: Set(None),
+        amount_layout: Set(import_profile::AMOUNT_SIGNED.to_string()),
+        debit_header: Set("".to_string()),
+        debit_index: Set(None),
+        credit_header: Set("".to_string()),
+        credit_index: Set(None),
+        direction_header: Set("".to_string()),
+        direction_index: Set(None),
+        debit_markers: Set("D|Dare|Addebito|Uscita".to_string()),
+        invert_sign: Set(false),
+        worksheet: Set("".to_string()),
+        end_marker: Set("".to_string()),
+        stop_at_blank_row: Set(false),
+        skip_patterns: Set("".to_string()),
+        pdf_line_patterns: Set("".to_string()),
+        ..Default::default()
+    }
+}
+
+/// Profiles for the Excel exports of common Italian banks. Columns are found
+/// by header name, so no starter string or index is needed.
+pub fn builtin_profiles() -> Vec<import_profile::ActiveModel> {
+    let builtin = |name: &str, date: &str, description: &str| {
+        let mut profile = default_profile(name);
+        profile.builtin = Set(true);
+        profile.date_header = Set(date.to_string());
+        profile.description_header = Set(description.to_string());
+        profile
+    };
+
+    let mut intesa = builtin(
+        "Intesa Sanpaolo - Excel",
+        "Data contabile|Data",
+        "Operazione|Descrizione",
+    );
+    intesa.value_header = Set("Importo".to_string());
+    intesa.currency_header = Set("Divisa".to_string());
+
+    let mut unicredit = builtin(
+        "UniCredit - Excel",
+        "Data Registrazione|Data",
+        "Descrizione",
+    );
+    unicredit.value_header = Set("Importo (EUR)|Importo".to_string());
+
+    let mut fineco = builtin(
+        "Fineco - Excel",
+        "Data Operazione|Data",
+        "Descrizione Completa|Descrizione",
+    );
+    fineco.amount_layout = Set(import_profile::AMOUNT_SPLIT.to_string());
+    fineco.debit_header = Set("Uscite".to_string());
+    fineco.credit_header = Set("Entrate".to_string());
+
+    let mut bancoposta = builtin(
+        "BancoPosta - Excel",
+        "Data Contabile|Data",
+        "Descrizione operazioni|Descrizione",
+    );
+    bancoposta.amount_layout = Set(import_profile::AMOUNT_SPLIT.to_string());
+    bancoposta.debit_header = Set("Addebiti".to_string());
+    bancoposta.credit_header = Set("Accrediti".to_string());
+
+    vec![intesa, unicredit, fineco, bancoposta]
+}
+
+/// Inserts the built-in profiles missing from the database, matched by name.
+/// Run at startup and after a restore.
+pub async fn ensure_builtin_profiles(db: &DatabaseConnection) -> Result<(), DbErr> {
+    let existing: Vec<String> = import_profile::Entity::find()
+        .filter(import_profile::Column::Builtin.eq(true))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|p| p.name)
+        .collect();
+
+    for profile in builtin_profiles() {
+        if !existing.contains(profile.name.as_ref()) {
+            profile.insert(db).await?;
+        }
+    }
+
+    Ok(())
+}
@@ -1,4 +1,5 @@
 pub mod accounts;
 pub mod entities;
+pub mod import_profiles;
 
 pub use entities::*;
@@ -0,0 +1,244 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use chrono::Local;
+use sea_orm::DatabaseConnection;
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::{
+    database::{account, accounts::get_all_accounts},
+    routes::uploader::stage_statement_file,
+};
+
+const PROCESSED_DIR: &str = "processed";
+const FAILED_DIR: &str = "failed";
+const LOG_FILE: &str = "inbox.log";
+
+/// Files changed more recently than this may still be being downloaded.
+const SETTLE_TIME: Duration = Duration::from_secs(10);
+
+const DEFAULT_INTERVAL_SECONDS: u64 = 60;
+
+/// Starts the inbox watcher when `IMPORT_INBOX_DIR` is set: statement files
+/// dropped there are staged for review on the matching account, then moved
+/// to `processed` or `failed` with a line in `inbox.log`.
+///
+/// A file belongs to the account named by its subfolder or, for files at the
+/// top level, by the part of the filename before the first `_`. Accounts are
+/// matched by id or by name, ignoring case, spaces and punctuation, so
+/// `contobp_2026-01.csv` and `Conto BP/estratto.xlsx` both go to "Conto BP".
+/// The folder is scanned every `IMPORT_INBOX_INTERVAL` seconds, 60 by default
+/// or when the value is not a positive number.
+pub async fn spawn_watcher(db: DatabaseConnection) -> anyhow::Result<()> {
+    let Ok(dir) = std::env::var("IMPORT_INBOX_DIR") else {
+        return Ok(());
+    };
+    let interval = match std::env::var("IMPORT_INBOX_INTERVAL") {
+        Ok(seconds) => match seconds.trim().parse::<u64>() {
+            Ok(seconds) if seconds > 0 => Duration::from_secs(seconds),
+            _ => {
+                eprintln!(
+                    "IMPORT_INBOX_INTERVAL non valido '{}', uso {} secondi",
+                    seconds, DEFAULT_INTERVAL_SECONDS
+                );
+                Duration::from_secs(DEFAULT_INTERVAL_SECONDS)
+            }
+        },
+        Err(_) => Duration::from_secs(DEFAULT_INTERVAL_SECONDS),
+    };
+
+    let inbox = PathBuf::from(dir);
+    fs::create_dir_all(inbox.join(PROCESSED_DIR)).await?;
+    fs::create_dir_all(inbox.join(FAILED_DIR)).await?;
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = scan_inbox(&db, &inbox).await {
+                eprintln!("Errore nella lettura della cartella di import: {:?}", e);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn account_key(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn find_account<'a>(accounts: &'a [account::Model], name: &str) -> Option<&'a account::Model> {
+    let key = account_key(name);
+    if key.is_empty() {
+        return None;
+    }
+
+    accounts
+        .iter()
+        .find(|a| a.id.to_string() == key || account_key(&a.name) == key)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Regular files that are neither hidden nor still being written.
+async fn ready_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        let settled = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age >= SETTLE_TIME);
+        if metadata.is_file() && settled && !file_name(&entry.path()).starts_with('.') {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+async fn scan_inbox(db: &DatabaseConnection, inbox: &Path) -> anyhow::Result<()> {
+    let accounts = get_all_accounts(db).await?;
+
+    for path in ready_files(inbox).await? {
+        let name = file_name(&path);
+        if name == LOG_FILE {
+            continue;
+        }
+        let prefix = name.split_once('_').map(|(prefix, _)| prefix);
+        let account = prefix.and_then(|prefix| find_account(&accounts, prefix));
+        import_file_or_log(db, inbox, &path, account).await;
+    }
+
+    let mut entries = fs::read_dir(inbox).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = file_name(&entry.path());
+        if !entry.file_type().await?.is_dir()
+            || name.starts_with('.')
+            || name == PROCESSED_DIR
+            || name == FAILED_DIR
+        {
+            continue;
+        }
+
+        let account = find_account(&accounts, &name);
+        let files = match ready_files(&entry.path()).await {
+            Ok(files) => files,
+            Err(e) => {
+                eprintln!("Errore nella lettura della cartella {}: {:?}", name, e);
+                continue;
+            }
+        };
+        for path in files {
+            import_file_or_log(db, inbox, &path, account).await;
+        }
+    }
+
+    Ok(())
+}
+
+/// Filesystem errors on one file are logged and the scan goes on with the
+/// next one.
+async fn import_file_or_log(
+    db: &DatabaseConnection,
+    inbox: &Path,
+    path: &Path,
+    account: Option<&account::Model>,
+) {
+    if let Err(e) = import_file(db, inbox, path, account).await {
+        eprintln!(
+            "Errore nell'import del file {} dalla cartella di import: {:?}",
+            path.display(),
+            e
+        );
+    }
+}
+
+/// Stages one file, moves it out of the inbox and logs the outcome. Only
+/// filesystem errors are returned, so a bad statement never stops the scan.
+async fn import_file(
+    db: &DatabaseConnection,
+    inbox: &Path,
+    path: &Path,
+    account: Option<&account::Model>,
+) -> anyhow::Result<()> {
+    let source = path
+        .strip_prefix(inbox)
+        .unwrap_or(path)
+        .display()
+        .to_string();
+    let filename = file_name(path);
+
+    let outcome = match account {
+        Some(account) => {
+            let data = fs::read(path).await?;
+            stage_statement_file(db, account.id, &filename, &data)
+                .await
+                .map(|summary| {
+                    format!(
+                        "{} righe da rivedere su {} ({} duplicati saltati, {} da verificare)",
+                        summary.rows_staged,
+                        summary.review_url,
+                        summary.duplicates_skipped,
+                        summary.duplicates_flagged
+                    )
+                })
+        }
+        None => Err("Nessun account corrisponde al nome del file o della cartella".to_string()),
+    };
+
+    let now = Local::now();
+    let folder = if outcome.is_ok() {
+        PROCESSED_DIR
+    } else {
+        FAILED_DIR
+    };
+    let stamp = now.format("%Y%m%d%H%M%S");
+    let mut target = inbox.join(folder).join(format!("{}_{}", stamp, filename));
+    let mut copy = 1;
+    while fs::try_exists(&target).await? {
+        target = inbox
+            .join(folder)
+            .join(format!("{}_{}_{}", stamp, copy, filename));
+        copy += 1;
+    }
+    fs::rename(path, &target).await?;
+
+    let account_name = account.map(|a| a.name.as_str()).unwrap_or("-");
+    let line = match outcome {
+        Ok(result) => format!(
+            "{} OK {} [{}] -> {}\n",
+            now.format("%Y-%m-%d %H:%M:%S"),
+            source,
+            account_name,
+            result
+        ),
+        Err(message) => format!(
+            "{} ERRORE {} [{}] -> {}\n",
+            now.format("%Y-%m-%d %H:%M:%S"),
+            source,
+            account_name,
+            message
+        ),
+    };
+    let mut log = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(inbox.join(LOG_FILE))
+        .await?;
+    log.write_all(line.as_bytes()).await?;
+
+    Ok(())
+}
@@ -4,13 +4,17 @@ use std::net::SocketAddr;
 use tokio::net::TcpListener;
 
 mod database;
+mod inbox;
 mod routes;
+mod statement_store;
 use crate::routes::routes::router;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let database_url = std::env::var("DATABASE_URL")?;
     let db = Database::connect(&database_url).await?;
+    database::import_profiles::ensure_builtin_profiles(&db).await?;
+    inbox::spawn_watcher(db.clone()).await?;
 
     let app = router().layer(Extension(db));
 
@@ -0,0 +1,861 @@
+use std::collections::{HashMap, HashSet};
+
+use askama::Template;
+use axum::{
+    extract::{Extension, Path},
+    http::{header, Response, StatusCode},
+    response::{Html, IntoResponse},
+    Form, Json,
+};
+use chrono::{NaiveDate, Utc};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, ConnectionTrait, DatabaseConnection,
+    EntityTrait, QueryFilter, QueryOrder, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::{account, category, import_batch, import_file, import_row, settings, transaction},
+    routes::{
+        account_rules::{categorize_new_transaction, get_active_rules, RuleMatcher, RuleOutcome},
+        account_transactions::empty_string_as_none,
+        running_balance::{reconcile, BalanceEntry},
+        uploader::{DuplicateIndex, DuplicateStatus, TransactionData},
+    },
+    statement_store,
+};
+
+#[derive(Template)]
+#[template(path = "account_imports.html")]
+struct AccountImportsTemplate<'a> {
+    account: account::Model,
+    batches: Vec<BatchWithCounts>,
+    history: Vec<BatchWithFiles>,
+    menu: &'a str,
+    sub_menu: &'a str,
+}
+
+struct BatchWithCounts {
+    model: import_batch::Model,
+    files: Vec<import_file::Model>,
+    rows: usize,
+    dropped: usize,
+}
+
+struct BatchWithFiles {
+    model: import_batch::Model,
+    files: Vec<import_file::Model>,
+}
+
+#[derive(Template)]
+#[template(path = "account_import_review.html")]
+struct ImportReviewTemplate<'a> {
+    account: account::Model,
+    batch: import_batch::Model,
+    rows: Vec<ReviewRow>,
+    categories: Vec<category::Model>,
+    menu: &'a str,
+    sub_menu: &'a str,
+}
+
+struct ReviewRow {
+    model: import_row::Model,
+    category_name: String,
+    matching_rules: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ImportRowForm {
+    date: String,
+    description: String,
+    value: f64,
+    label: String,
+    #[serde(deserialize_with = "empty_string_as_none")]
+    category_id: Option<i32>,
+}
+
+#[derive(Serialize)]
+struct CommitSummary {
+    rows_imported: usize,
+    duplicates_skipped: usize,
+    /// Imported rows resembling a transaction that reached the ledger after
+    /// they were staged.
+    duplicates_flagged: usize,
+    /// Imported rows categorized by the account rules.
+    auto_categorized: usize,
+    /// Imported rows left without a category.
+    uncategorized: usize,
+    /// Imported rows matched by more than one rule, to be resolved by hand.
+    rule_conflicts: usize,
+    /// Where the conflicts are resolved, only set when there are some.
+    conflicts_url: Option<String>,
+    /// Closing balance recorded for the account, when the running balance
+    /// of the whole statement reconciled.
+    verified_balance: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct RollbackSummary {
+    transactions_deleted: u64,
+}
+
+/// Loads a batch of the account that is still waiting for review, staged
+/// rows can't be touched once the batch has been committed.
+async fn find_staged_batch(
+    db: &DatabaseConnection,
+    account_id: i32,
+    batch_id: i32,
+) -> Result<import_batch::Model, StatusCode> {
+    let batch = import_batch::Entity::find_by_id(batch_id)
+        .filter(import_batch::Column::AccountId.eq(account_id))
+        .one(db)
+        .await
+        .map_err(|e| {
+            eprintln!("Errore nel recupero dell'import: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if batch.status != import_batch::STATUS_STAGED {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    Ok(batch)
+}
+
+async fn find_staged_row(
+    db: &DatabaseConnection,
+    account_id: i32,
+    batch_id: i32,
+    row_id: i32,
+) -> Result<import_row::Model, StatusCode> {
+    find_staged_batch(db, account_id, batch_id).await?;
+
+    import_row::Entity::find_by_id(row_id)
+        .filter(import_row::Column::BatchId.eq(batch_id))
+        .one(db)
+        .await
+        .map_err(|e| {
+            eprintln!("Errore nel recupero della riga importata: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+pub async fn get_account_imports_handler(
+    Path(account_id): Path<i32>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> Result<Html<String>, StatusCode> {
+    let account_data = account::Entity::find_by_id(account_id)
+        .one(&db)
+        .await
+        .map_err(|e| {
+            eprintln!("Errore nel recupero account: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let batches_with_rows = import_batch::Entity::find()
+        .filter(import_batch::Column::AccountId.eq(account_id))
+        .filter(import_batch::Column::Status.eq(import_batch::STATUS_STAGED))
+        .order_by_desc(import_batch::Column::CreatedAt)
+        .find_with_related(import_row::Entity)
+        .all(&db)
+        .await
+        .map_err(|e| {
+            eprintln!("Errore nel recupero degli import: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let history = import_batch::Entity::find()
+        .filter(import_batch::Column::AccountId.eq(account_id))
+        .filter(import_batch::Column::Status.ne(import_batch::STATUS_STAGED))
+        .order_by_desc(import_batch::Column::CreatedAt)
+        .all(&db)
+        .await
+        .map_err(|e| {
+            eprintln!("Errore nel recupero dello storico import: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let mut files_by_batch: HashMap<i32, Vec<import_file::Model>> = HashMap::new();
+    for file in import_file::Entity::find()
+        .inner_join(import_batch::Entity)
+        .filter(import_batch::Column::AccountId.eq(account_id))
+        .order_by_asc(import_file::Column::Position)
+        .all(&db)
+        .await
+        .map_err(|e| {
+            eprintln!("Errore nel recupero dei file degli import: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+    {
+        files_by_batch.entry(file.batch_id).or_default().push(file);
+    }
+
+    let batches = batches_with_rows
+        .into_iter()
+        .map(|(model, rows)| BatchWithCounts {
+            files: files_by_batch.remove(&model.id).unwrap_or_default(),
+            model,
+            rows: rows.len(),
+            dropped: rows.iter().filter(|r| r.dropped).count(),
+        })
+        .collect();
+
+    let history = history
+        .into_iter()
+        .map(|model| BatchWithFiles {
+            files: files_by_batch.remove(&model.id).unwrap_or_default(),
+            model,
+        })
+        .collect();
+
+    let html = AccountImportsTemplate {
+        account: account_data,
+        batches,
+        history,
+        menu: "accounts",
+        sub_menu: "imports",
+    };
+
+    Ok(Html(html.render().unwrap()))
+}
+
+pub async fn get_import_batch_handler(
+    Path((account_id, batch_id)): Path<(i32, i32)>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> Result<Html<String>, StatusCode> {
+    let account_data = account::Entity::find_by_id(account_id)
+        .one(&db)
+        .await
+        .map_err(|e| {
+            eprintln!("Errore nel recupero account: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let batch = find_staged_batch(&db, account_id, batch_id).await?;
+
+    let rows_with_cats = import_row::Entity::find()
+        .filter(import_row::Column::BatchId.eq(batch_id))
+        .order_by_asc(import_row::Column::Position)
+        .find_also_related(category::Entity)
+        .all(&db)
+        .await
+        .map_err(|e| {
+            eprintln!("Errore nel recupero delle righe importate: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let active_rules = get_active_rules(&db, account_id).await.map_err(|e| {
+        eprintln!("Errore nel recupero delle regole attive: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let matcher = RuleMatcher::new(active_rules).map_err(|e| {
+        eprintln!("Errore nella compilazione delle regole: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let rows = rows_with_cats
+        .into_iter()
+        .map(|(model, cat)| {
+            // Rules work on ledger transactions, so the draft row is dressed up
+            // as one to see which rules would fire once committed.
+            let probe = transaction::Model {
+                id: 0,
+                account_id,
+                category_id: model.category_id,
+                value: model.value,
+                description: model.description.clone(),
+                date: model.date.and_hms_opt(0, 0, 0).unwrap(),
+                perc_to_exclude: 0.0,
+                label: model.label.clone(),
+                external_id: model.external_id.clone(),
+                import_batch_id: Some(batch_id),
+            };
+            let matching_rules = matcher
+                .matching(&probe)
+                .into_iter()
+                .map(|r| r.name)
+                .collect();
+
+            ReviewRow {
+                model,
+                category_name: cat.map(|c| c.category).unwrap_or_else(|| "-".to_string()),
+                matching_rules,
+            }
+        })
+        .collect();
+
+    let categories = category::Entity::find().all(&db).await.map_err(|e| {
+        eprintln!("Errore find categories: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let html = ImportReviewTemplate {
+        account: account_data,
+        batch,
+        rows,
+        categories,
+        menu: "accounts",
+        sub_menu: "imports",
+    };
+
+    Ok(Html(html.render().unwrap()))
+}
+
+pub async fn edit_import_row_handler(
+    Path((account_id, batch_id, row_id)): Path<(i32, i32, i32)>,
+    Extension(db): Extension<DatabaseConnection>,
+    Form(form): Form<ImportRowForm>,
+) -> Result<StatusCode, StatusCode> {
+    let row = find_staged_row(&db, account_id, batch_id, row_id).await?;
+
+    let date =
+        NaiveDate::parse_from_str(&form.date, "%Y-%m-%d").map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let mut the_row: import_row::ActiveModel = row.into();
+    the_row.date = Set(date);
+    the_row.description = Set(form.description);
+    the_row.value = Set(form.value);
+    the_row.label = Set(form.label);
+    the_row.category_id = Set(form.category_id);
+    the_row.update(&db).await.map_err(|err| {
+        eprintln!("Cannot update import row: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn set_row_dropped(
+    db: &DatabaseConnection,
+    account_id: i32,
+    batch_id: i32,
+    row_id: i32,
+    dropped: bool,
+) -> Result<StatusCode, StatusCode> {
+    let row = find_staged_row(db, account_id, batch_id, row_id).await?;
+
+    let mut the_row: import_row::ActiveModel = row.into();
+    the_row.dropped = Set(dropped);
+    the_row.update(db).await.map_err(|err| {
+        eprintln!("Cannot update import row: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(StatusCode::OK)
+}
+
+pub async fn drop_import_row_handler(
+    Path((account_id, batch_id, row_id)): Path<(i32, i32, i32)>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> Result<StatusCode, StatusCode> {
+    set_row_dropped(&db, account_id, batch_id, row_id, true).await
+}
+
+pub async fn restore_import_row_handler(
+    Path((account_id, batch_id, row_id)): Path<(i32, i32, i32)>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> Result<StatusCode, StatusCode> {
+    set_row_dropped(&db, account_id, batch_id, row_id, false).await
+}
+
+/// The row as seen by the duplicate check. External ids are checked on their
+/// own at commit time, so they are left out.
+fn row_data(row: &import_row::Model) -> TransactionData {
+    TransactionData {
+        description: row.description.clone(),
+        value: row.value,
+        date: row.date,
+        ..Default::default()
+    }
+}
+
+/// Checks a kept row against the ledger again: another batch may have
+/// brought the same movement in since the row was staged. What the user
+/// already decided during the review stands, a restored duplicate is a
+/// movement of its own and a near-duplicate was already looked at.
+fn recheck_row(duplicates: &mut DuplicateIndex, row: &import_row::Model) -> DuplicateStatus {
+    match duplicates.check(&row_data(row)) {
+        DuplicateStatus::Duplicate if row.duplicate_status == import_row::DUPLICATE => {
+            DuplicateStatus::Unique
+        }
+        DuplicateStatus::NearDuplicate(_) if row.duplicate_status != import_row::UNIQUE => {
+            DuplicateStatus::Unique
+        }
+        status => status,
+    }
+}
+
+/// Moves the rows the user kept into the ledger, all or nothing. Rows whose
+/// external id or fingerprint reached the account after staging (e.g. from
+/// another batch of the same statement) are skipped, rows resembling one are
+/// imported and flagged.
+pub async fn commit_import_batch_handler(
+    Path((account_id, batch_id)): Path<(i32, i32)>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> impl IntoResponse {
+    let batch = match find_staged_batch(&db, account_id, batch_id).await {
+        Ok(batch) => batch,
+        Err(status) => return status.into_response(),
+    };
+
+    let result: Result<CommitSummary, sea_orm::DbErr> = async {
+        let account_settings = settings::Entity::find()
+            .filter(settings::Column::AccountId.eq(account_id))
+            .one(&db)
+            .await?;
+        let auto_apply_rules = account_settings
+            .as_ref()
+            .is_some_and(|s| s.auto_apply_rules);
+        let policy = account_settings
+            .as_ref()
+            .map(|s| s.rule_conflict_policy.clone())
+            .unwrap_or_else(|| settings::CONFLICT_ASK.to_string());
+        let rules = if auto_apply_rules {
+            get_active_rules(&db, account_id).await?
+        } else {
+            Vec::new()
+        };
+        let rules = RuleMatcher::new(rules).map_err(|e| sea_orm::DbErr::Custom(e.to_string()))?;
+
+        let txn = db.begin().await?;
+
+        let rows = import_row::Entity::find()
+            .filter(import_row::Column::BatchId.eq(batch.id))
+            .filter(import_row::Column::Dropped.eq(false))
+            .order_by_asc(import_row::Column::Position)
+            .all(&txn)
+            .await?;
+
+        let external_ids: Vec<String> = rows.iter().filter_map(|r| r.external_id.clone()).collect();
+        let mut known_external_ids: HashSet<String> = if external_ids.is_empty() {
+            HashSet::new()
+        } else {
+            transaction::Entity::find()
+                .filter(transaction::Column::AccountId.eq(account_id))
+                .filter(transaction::Column::ExternalId.is_in(external_ids))
+                .all(&txn)
+                .await?
+                .into_iter()
+                .filter_map(|t| t.external_id)
+                .collect()
+        };
+
+        let data: Vec<TransactionData> = rows.iter().map(row_data).collect();
+        let mut duplicates = DuplicateIndex::load(&txn, account_id, &data).await?;
+
+        let mut summary = CommitSummary {
+            rows_imported: 0,
+            duplicates_skipped: 0,
+            duplicates_flagged: 0,
+            auto_categorized: 0,
+            uncategorized: 0,
+            rule_conflicts: 0,
+            conflicts_url: None,
+            verified_balance: None,
+        };
+
+        for row in rows {
+            if let Some(external_id) = &row.external_id {
+                if !known_external_ids.insert(external_id.clone()) {
+                    summary.duplicates_skipped += 1;
+                    continue;
+                }
+            }
+
+            let row = match recheck_row(&mut duplicates, &row) {
+                DuplicateStatus::Unique => row,
+                DuplicateStatus::Duplicate => {
+                    summary.duplicates_skipped += 1;
+                    let mut the_row: import_row::ActiveModel = row.into();
+                    the_row.duplicate_status = Set(import_row::DUPLICATE.to_string());
+                    the_row.dropped = Set(true);
+                    the_row.update(&txn).await?;
+                    continue;
+                }
+                DuplicateStatus::NearDuplicate(existing_transaction_id) => {
+                    summary.duplicates_flagged += 1;
+                    let mut the_row: import_row::ActiveModel = row.into();
+                    the_row.duplicate_status = Set(import_row::NEAR_DUPLICATE.to_string());
+                    the_row.duplicate_of = Set(Some(existing_transaction_id));
+                    the_row.update(&txn).await?
+                }
+            };
+
+            let inserted = transaction::ActiveModel {
+                account_id: Set(account_id),
+                category_id: Set(row.category_id),
+                value: Set(row.value),
+                description: Set(row.description),
+                date: Set(row.date.into()),
+                perc_to_exclude: Set(0.0),
+                label: Set(row.label),
+                external_id: Set(row.external_id),
+                import_batch_id: Set(Some(batch.id)),
+                ..Default::default()
+            }
+            .insert(&txn)
+            .await?;
+            summary.rows_imported += 1;
+
+            // Rows categorized during the review keep the user's choice.
+            if inserted.category_id.is_some() {
+                continue;
+            }
+
+            match categorize_new_transaction(&txn, inserted, &rules, &policy).await? {
+                RuleOutcome::Categorized => summary.auto_categorized += 1,
+                RuleOutcome::NoMatch => summary.uncategorized += 1,
+                RuleOutcome::Conflict => {
+                    summary.uncategorized += 1;
+                    summary.rule_conflicts += 1;
+                }
+            }
+        }
+
+        if summary.rule_conflicts > 0 {
+            summary.conflicts_url = Some(format!("/accounts/{}/rules", account_id));
+        }
+
+        // Dropped rows are still part of the statement, its running balance
+        // is checked on all of them. The closing balance becomes the starting
+        // point of the next import only when every row adds up and the
+        // statement is not older than the last verified one.
+        let statement_rows = import_row::Entity::find()
+            .filter(import_row::Column::BatchId.eq(batch.id))
+            .order_by_asc(import_row::Column::Position)
+            .all(&txn)
+            .await?;
+        let entries: Vec<BalanceEntry> = statement_rows
+            .iter()
+            .map(|r| BalanceEntry {
+                date: r.date,
+                description: &r.description,
+                value: r.value,
+                balance: r.balance,
+            })
+            .collect();
+        let running_balance = reconcile(&entries);
+        let mut the_batch: import_batch::ActiveModel = batch.into();
+        if let (true, Some((closing_date, closing_balance))) =
+            (running_balance.breaks.is_empty(), running_balance.closing)
+        {
+            let newer = account_settings
+                .as_ref()
+                .and_then(|s| s.verified_balance_date)
+                .is_none_or(|verified_date| closing_date >= verified_date);
+            if newer {
+                the_batch.verified_balance = Set(Some(closing_balance));
+                the_batch.verified_balance_date = Set(Some(closing_date));
+                the_batch.previous_verified_balance =
+                    Set(account_settings.as_ref().and_then(|s| s.verified_balance));
+                the_batch.previous_verified_balance_date = Set(account_settings
+                    .as_ref()
+                    .and_then(|s| s.verified_balance_date));
+
+                let mut the_settings: settings::ActiveModel = match account_settings {
+                    Some(account_settings) => account_settings.into(),
+                    None => settings::ActiveModel {
+                        account_id: Set(account_id),
+                        profile_id: Set(None),
+                        auto_apply_rules: Set(false),
+                        ..Default::default()
+                    },
+                };
+                the_settings.verified_balance = Set(Some(closing_balance));
+                the_settings.verified_balance_date = Set(Some(closing_date));
+                the_settings.save(&txn).await?;
+                summary.verified_balance = Some(closing_balance);
+            }
+        }
+
+        the_batch.status = Set(import_batch::STATUS_COMMITTED.to_string());
+        the_batch.rows_imported = Set(summary.rows_imported as i32);
+        the_batch.duplicates_skipped = Set(summary.duplicates_skipped as i32);
+        the_batch.committed_at = Set(Some(Utc::now().naive_utc()));
+        the_batch.update(&txn).await?;
+
+        txn.commit().await?;
+        Ok(summary)
+    }
+    .await;
+
+    match result {
+        Ok(summary) => (StatusCode::OK, Json(summary)).into_response(),
+        Err(e) => {
+            eprintln!("Errore nel commit dell'import {}: {:?}", batch_id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Errore nel commit dell'import",
+            )
+                .into_response()
+        }
+    }
+}
+
+pub async fn discard_import_batch_handler(
+    Path((account_id, batch_id)): Path<(i32, i32)>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> impl IntoResponse {
+    if let Err(status) = find_staged_batch(&db, account_id, batch_id).await {
+        return status;
+    }
+
+    match import_batch::Entity::delete_by_id(batch_id).exec(&db).await {
+        Ok(_) => StatusCode::NO_CONTENT,
+        Err(err) => {
+            eprintln!("Errore eliminando l'import {}: {}", batch_id, err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Puts back the verified balance the commit of the batch replaced, unless a
+/// later import or the user changed it since.
+pub async fn restore_verified_balance<C: ConnectionTrait>(
+    db: &C,
+    batch: &import_batch::Model,
+) -> Result<(), sea_orm::DbErr> {
+    if batch.verified_balance.is_none() {
+        return Ok(());
+    }
+    let account_settings = match settings::Entity::find()
+        .filter(settings::Column::AccountId.eq(batch.account_id))
+        .one(db)
+        .await?
+    {
+        Some(account_settings) => account_settings,
+        None => return Ok(()),
+    };
+    if account_settings.verified_balance != batch.verified_balance
+        || account_settings.verified_balance_date != batch.verified_balance_date
+    {
+        return Ok(());
+    }
+
+    let mut the_settings: settings::ActiveModel = account_settings.into();
+    the_settings.verified_balance = Set(batch.previous_verified_balance);
+    the_settings.verified_balance_date = Set(batch.previous_verified_balance_date);
+    the_settings.update(db).await?;
+    Ok(())
+}
+
+/// Deletes every transaction a committed batch created, in one database
+/// transaction, and puts back the verified balance its commit replaced. The
+/// batch stays in the history marked as rolled back.
+pub async fn rollback_import_batch_handler(
+    Path((account_id, batch_id)): Path<(i32, i32)>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> impl IntoResponse {
+    let batch = match import_batch::Entity::find_by_id(batch_id)
+        .filter(import_batch::Column::AccountId.eq(account_id))
+        .one(&db)
+        .await
+    {
+        Ok(Some(batch)) => batch,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Errore nel recupero dell'import: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if batch.status != import_batch::STATUS_COMMITTED {
+        return (StatusCode::CONFLICT, "Import non annullabile").into_response();
+    }
+
+    let result: Result<u64, sea_orm::DbErr> = async {
+        let txn = db.begin().await?;
+
+        let deleted = transaction::Entity::delete_many()
+            .filter(transaction::Column::ImportBatchId.eq(batch.id))
+            .exec(&txn)
+            .await?
+            .rows_affected;
+        restore_verified_balance(&txn, &batch).await?;
+
+        let mut the_batch: import_batch::ActiveModel = batch.into();
+        the_batch.status = Set(import_batch::STATUS_ROLLED_BACK.to_string());
+        the_batch.update(&txn).await?;
+
+        txn.commit().await?;
+        Ok(deleted)
+    }
+    .await;
+
+    match result {
+        Ok(transactions_deleted) => (
+            StatusCode::OK,
+            Json(RollbackSummary {
+                transactions_deleted,
+            }),
+        )
+            .into_response(),
+        Err(e) => {
+            eprintln!("Errore nell'annullamento dell'import {}: {:?}", batch_id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Errore nell'annullamento dell'import",
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Serves a statement file of an import as it was received.
+pub async fn download_import_file_handler(
+    Path((account_id, batch_id, file_id)): Path<(i32, i32, i32)>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> impl IntoResponse {
+    let file = match import_file::Entity::find_by_id(file_id)
+        .filter(import_file::Column::BatchId.eq(batch_id))
+        .find_also_related(import_batch::Entity)
+        .one(&db)
+        .await
+    {
+        Ok(Some((file, Some(batch)))) if batch.account_id == account_id => file,
+        Ok(_) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Errore nel recupero del file dell'import: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let data = match statement_store::load(&file.file_hash).await {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("Errore leggendo il file {}: {:?}", file.filename, e);
+            return (StatusCode::NOT_FOUND, "File originale non disponibile").into_response();
+        }
+    };
+
+    let filename: String = file
+        .filename
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || ".-_".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        )
+        .body(data.into())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn staged_row(id: i32, day: &str, value: f64, description: &str) -> import_row::Model {
+        import_row::Model {
+            id,
+            batch_id: 1,
+            position: id,
+            date: date(day),
+            description: description.to_string(),
+            value,
+            label: String::new(),
+            category_id: None,
+            external_id: None,
+            duplicate_status: import_row::UNIQUE.to_string(),
+            duplicate_of: None,
+            dropped: false,
+            currency: None,
+            balance: None,
+        }
+    }
+
+    /// Commits the rows into `ledger` the way the handler does, returning what
+    /// happened to each of them.
+    fn commit(
+        ledger: &mut Vec<transaction::Model>,
+        rows: &[import_row::Model],
+    ) -> Vec<&'static str> {
+        let mut duplicates = DuplicateIndex::default();
+        for transaction in ledger.iter() {
+            duplicates.record(transaction);
+        }
+
+        let mut outcomes = Vec::new();
+        for row in rows {
+            let outcome = match recheck_row(&mut duplicates, row) {
+                DuplicateStatus::Duplicate => {
+                    outcomes.push("skipped");
+                    continue;
+                }
+                DuplicateStatus::NearDuplicate(_) => "flagged",
+                DuplicateStatus::Unique => "imported",
+            };
+            outcomes.push(outcome);
+            ledger.push(transaction::Model {
+                id: ledger.len() as i32 + 1,
+                account_id: 1,
+                category_id: None,
+                value: row.value,
+                description: row.description.clone(),
+                date: row.date.and_hms_opt(0, 0, 0).unwrap(),
+                perc_to_exclude: 0.0,
+                label: String::new(),
+                external_id: None,
+                import_batch_id: Some(row.batch_id),
+            });
+        }
+        outcomes
+    }
+
+    #[test]
+    fn overlapping_batches_are_committed_once() {
+        // The same statement uploaded by hand and picked up from the inbox,
+        // both staged before either was committed.
+        let upload = vec![
+            staged_row(1, "2025-01-02", -12.5, "POS BAR ROMA"),
+            staged_row(2, "2025-01-03", -40.0, "ESSELUNGA"),
+            staged_row(3, "2025-01-03", -40.0, "ESSELUNGA"),
+        ];
+        let mut restored = staged_row(8, "2025-01-02", -12.5, "POS BAR ROMA");
+        restored.duplicate_status = import_row::DUPLICATE.to_string();
+        let inbox = vec![
+            staged_row(4, "2025-01-02", -12.5, "POS BAR ROMA"),
+            staged_row(5, "2025-01-03", -40.0, "ESSELUNGA"),
+            staged_row(6, "2025-01-03", -40.0, "ESSELUNGA"),
+            staged_row(7, "2025-01-03", -40.0, "ESSELUNGA"),
+            staged_row(9, "2025-01-02", -12.5, "BAR ROMA 0042"),
+            staged_row(10, "2025-01-05", -8.0, "EDICOLA"),
+            restored,
+        ];
+
+        let mut ledger = Vec::new();
+        assert_eq!(
+            commit(&mut ledger, &upload),
+            vec!["imported", "imported", "imported"]
+        );
+        assert_eq!(
+            commit(&mut ledger, &inbox),
+            vec![
+                "skipped", "skipped", "skipped",
+                // A third identical movement is not in the ledger yet.
+                "flagged", "flagged", "imported",
+                // Kept by the user as a movement of its own.
+                "imported",
+            ]
+        );
+        assert_eq!(ledger.len(), 7);
+    }
+}
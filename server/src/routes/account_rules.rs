@@ -2,19 +2,24 @@ use std::collections::HashSet;
 
 use crate::database::{
     category,
-    entities::{account, account_rule, rule},
+    entities::{account, account_rule, rule, settings},
     transaction,
 };
+use crate::routes::account_transactions::empty_string_as_none;
+use crate::routes::rule_conditions::{
+    parse_conditions, AmountCondition, CompiledCondition, Condition, DateRange, Facts, Sign,
+};
 use axum::{
     extract::{Extension, Path},
     http::StatusCode,
     response::{Html, IntoResponse, Redirect},
     Form, Json,
 };
-use chrono::{NaiveDate, NaiveDateTime};
-use regex::Regex;
+use chrono::NaiveDate;
+use regex::{Regex, RegexSet};
 use sea_orm::{
-    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, ConnectionTrait, DatabaseConnection,
+    EntityTrait, QueryFilter,
 };
 
 use askama::Template;
@@ -33,6 +38,9 @@ struct AccountRulesTemplate<'a> {
 
 struct RuleWithStatus {
     model: rule::Model,
+    /// One pattern per line.
+    patterns: String,
+    amount: String,
     active: bool,
 }
 
@@ -43,6 +51,10 @@ pub struct PreviewTransaction {
     value: f64,
     date: String,
     conflicts: Vec<rule::Model>,
+    /// Name of the rule that will be applied, none when the user must choose.
+    winning_rule: Option<String>,
+    /// Why the rule was chosen, or why the user must choose.
+    reason: String,
     label_old_value: String,
     label_new_value: String,
     perc_to_exclude_old_value: f32,
@@ -57,9 +69,27 @@ pub struct AddRuleForm {
     label: String,
     percentage: f32,
     category_id: i32,
-    regexpr: Option<String>,
+    patterns: Option<String>,
+    patterns_case_insensitive: Option<String>,
     date_start: Option<String>,
     date_end: Option<String>,
+    amount_min: Option<String>,
+    amount_max: Option<String>,
+    amount_exact: Option<String>,
+    amount_tolerance: Option<String>,
+    amount_sign: Option<String>,
+    conditions: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    priority: Option<i32>,
+}
+
+/// Amount conditions of a rule, read from the rule forms.
+pub struct AmountConditions {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub exact: Option<f64>,
+    pub tolerance: Option<f64>,
+    pub sign: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -103,6 +133,8 @@ pub async fn get_account_rules_handler(
         .map(|r| {
             let id = r.id;
             RuleWithStatus {
+                amount: describe_amount_conditions(&r),
+                patterns: r.patterns.0.join("\n"),
                 model: r,
                 active: active_rule_ids.contains(&id),
             }
@@ -168,11 +200,11 @@ pub async fn add_account_rule_handler(
     Path(account_id): Path<i32>,
     Extension(db): Extension<DatabaseConnection>,
     Form(form): Form<AddRuleForm>,
-) -> Result<Redirect, axum::http::StatusCode> {
+) -> Result<Redirect, (StatusCode, String)> {
     let date_start = match &form.date_start {
         Some(s) if !s.is_empty() => Some(
             NaiveDate::parse_from_str(s, "%Y-%m-%d")
-                .map_err(|_| axum::http::StatusCode::BAD_REQUEST)?,
+                .map_err(|_| invalid_rule(format!("Data di inizio non valida '{}'", s)))?,
         ),
         _ => None,
     };
@@ -180,26 +212,52 @@ pub async fn add_account_rule_handler(
     let date_end = match &form.date_end {
         Some(s) if !s.is_empty() => Some(
             NaiveDate::parse_from_str(s, "%Y-%m-%d")
-                .map_err(|_| axum::http::StatusCode::BAD_REQUEST)?,
+                .map_err(|_| invalid_rule(format!("Data di fine non valida '{}'", s)))?,
         ),
         _ => None,
     };
 
+    let patterns_case_insensitive = form.patterns_case_insensitive.is_some();
+    let patterns = parse_patterns_field(
+        form.patterns.as_deref().unwrap_or(""),
+        patterns_case_insensitive,
+    )?;
+
+    let amount = parse_amount_conditions(
+        form.amount_min.as_deref().unwrap_or(""),
+        form.amount_max.as_deref().unwrap_or(""),
+        form.amount_exact.as_deref().unwrap_or(""),
+        form.amount_tolerance.as_deref().unwrap_or(""),
+        form.amount_sign.as_deref().unwrap_or(""),
+    )?;
+    let conditions = parse_conditions_field(form.conditions.as_deref().unwrap_or(""))?;
+
     let new_rule = rule::ActiveModel {
         name: Set(form.name),
         label: Set(form.label),
         percentage: Set(form.percentage),
         category_id: Set(form.category_id),
-        regexpr: Set(form.regexpr.clone()),
+        patterns: Set(rule::Patterns(patterns)),
+        patterns_case_insensitive: Set(patterns_case_insensitive),
         date_start: Set(date_start),
         date_end: Set(date_end),
+        amount_min: Set(amount.min),
+        amount_max: Set(amount.max),
+        amount_exact: Set(amount.exact),
+        amount_tolerance: Set(amount.tolerance),
+        amount_sign: Set(amount.sign),
+        conditions: Set(conditions),
+        priority: Set(form.priority.unwrap_or_default()),
 
         ..Default::default()
     };
 
     let inserted_rule = new_rule.insert(&db).await.map_err(|e| {
         eprintln!("Error inserting rule: {:?}", e);
-        axum::http::StatusCode::BAD_REQUEST
+        (
+            StatusCode::BAD_REQUEST,
+            "Errore nel salvataggio della regola".to_string(),
+        )
     })?;
 
     account_rule::ActiveModel {
@@ -209,52 +267,477 @@ pub async fn add_account_rule_handler(
     }
     .insert(&db)
     .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Errore nell'attivazione della regola".to_string(),
+        )
+    })?;
 
     Ok(Redirect::to(&format!("/accounts/{}/rules", account_id)))
 }
 
-fn get_applayable_rules(
-    transaction: transaction::Model,
-    rules: Vec<rule::Model>,
-) -> Vec<rule::Model> {
-    let mut appliers: Vec<rule::Model> = vec![];
-
-    'rules: for rule in rules {
-        if !rule.regexpr.is_none() {
-            let regexprs: Vec<&str> = rule.regexpr.as_deref().unwrap_or("").split(',').collect();
-
-            for regexpr in regexprs {
-                let re = Regex::new(regexpr).unwrap();
-                if re.is_match(&transaction.description) {
-                    appliers.push(rule.clone());
-                    continue 'rules;
-                }
+/// A rule form rejected, the message is shown to the user.
+fn invalid_rule(message: String) -> (StatusCode, String) {
+    eprintln!("{}", message);
+    (StatusCode::BAD_REQUEST, message)
+}
+
+fn parse_amount(raw: &str) -> Result<Option<f64>, (StatusCode, String)> {
+    let raw = raw.trim();
+    if raw.is_empty() {
+        return Ok(None);
+    }
+
+    raw.replace(',', ".")
+        .parse::<f64>()
+        .map(Some)
+        .map_err(|_| invalid_rule(format!("Importo non valido nella regola: '{}'", raw)))
+}
+
+/// The regular expression actually compiled for a description pattern.
+fn pattern_source(pattern: &str, case_insensitive: bool) -> String {
+    if case_insensitive {
+        format!("(?i:{})", pattern)
+    } else {
+        pattern.to_string()
+    }
+}
+
+/// Reads the description patterns of the rule forms, one per line, and
+/// checks every one of them is a valid regular expression.
+pub fn parse_patterns_field(
+    raw: &str,
+    case_insensitive: bool,
+) -> Result<Vec<String>, (StatusCode, String)> {
+    let mut patterns = Vec::new();
+    for (line, pattern) in raw.lines().enumerate() {
+        if pattern.trim().is_empty() {
+            continue;
+        }
+        Regex::new(&pattern_source(pattern, case_insensitive)).map_err(|e| {
+            invalid_rule(format!(
+                "Espressione regolare non valida alla riga {} '{}': {}",
+                line + 1,
+                pattern,
+                e
+            ))
+        })?;
+        patterns.push(pattern.to_string());
+    }
+    Ok(patterns)
+}
+
+/// Reads the amount fields of the rule forms, empty fields mean no
+/// condition. Amounts are positive, the sign has its own field.
+pub fn parse_amount_conditions(
+    min: &str,
+    max: &str,
+    exact: &str,
+    tolerance: &str,
+    sign: &str,
+) -> Result<AmountConditions, (StatusCode, String)> {
+    let conditions = AmountConditions {
+        min: parse_amount(min)?,
+        max: parse_amount(max)?,
+        exact: parse_amount(exact)?,
+        tolerance: parse_amount(tolerance)?,
+        sign: match sign.trim() {
+            "" => None,
+            s @ (rule::SIGN_INCOME | rule::SIGN_EXPENSE) => Some(s.to_string()),
+            s => {
+                return Err(invalid_rule(format!(
+                    "Segno non valido nella regola: '{}'",
+                    s
+                )))
             }
+        },
+    };
+
+    amount_condition(
+        conditions.min,
+        conditions.max,
+        conditions.exact,
+        conditions.tolerance,
+        conditions.sign.as_deref(),
+    )
+    .validate()
+    .map_err(invalid_rule)?;
+
+    Ok(conditions)
+}
+
+/// Reads the condition tree field of the rule forms, empty means none. The
+/// tree is stored in its canonical JSON form.
+pub fn parse_conditions_field(raw: &str) -> Result<Option<String>, (StatusCode, String)> {
+    if raw.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let condition = parse_conditions(raw).map_err(invalid_rule)?;
+    serde_json::to_string(&condition).map(Some).map_err(|e| {
+        eprintln!("Errore nella scrittura delle condizioni: {:?}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Errore nella scrittura delle condizioni".to_string(),
+        )
+    })
+}
+
+/// Checks a rule that didn't come through the rule forms, such as a draft
+/// sent as JSON, the way the forms would.
+pub fn validate_rule(rule: &rule::Model) -> Result<(), (StatusCode, String)> {
+    if let Some(sign) = rule
+        .amount_sign
+        .as_deref()
+        .filter(|s| ![rule::SIGN_INCOME, rule::SIGN_EXPENSE].contains(s))
+    {
+        return Err(invalid_rule(format!(
+            "Segno non valido nella regola: '{}'",
+            sign
+        )));
+    }
+
+    rule_condition(rule)
+        .and_then(|condition| condition.compile())
+        .map_err(invalid_rule)?;
+    Ok(())
+}
+
+fn amount_condition(
+    min: Option<f64>,
+    max: Option<f64>,
+    exact: Option<f64>,
+    tolerance: Option<f64>,
+    sign: Option<&str>,
+) -> AmountCondition {
+    AmountCondition {
+        min,
+        max,
+        exact,
+        tolerance,
+        sign: match sign {
+            Some(rule::SIGN_INCOME) => Some(Sign::Income),
+            Some(rule::SIGN_EXPENSE) => Some(Sign::Expense),
+            _ => None,
+        },
+    }
+}
+
+/// Short text of the amount conditions for the rule lists.
+pub fn describe_amount_conditions(rule: &rule::Model) -> String {
+    let mut parts = Vec::new();
+    match rule.amount_sign.as_deref() {
+        Some(rule::SIGN_INCOME) => parts.push("income".to_string()),
+        Some(rule::SIGN_EXPENSE) => parts.push("expense".to_string()),
+        _ => {}
+    }
+    if let Some(min) = rule.amount_min {
+        parts.push(format!(">= {:.2}", min));
+    }
+    if let Some(max) = rule.amount_max {
+        parts.push(format!("<= {:.2}", max));
+    }
+    if let Some(exact) = rule.amount_exact {
+        match rule.amount_tolerance {
+            Some(tolerance) => parts.push(format!("= {:.2} ± {:.2}", exact, tolerance)),
+            None => parts.push(format!("= {:.2}", exact)),
         }
+    }
+    parts.join(", ")
+}
+
+/// Date window, amount fields and condition tree of a rule, all of them
+/// must match.
+fn rule_filters(rule: &rule::Model) -> Result<Vec<Condition>, String> {
+    let mut filters = Vec::new();
+    if rule.date_start.is_some() || rule.date_end.is_some() {
+        filters.push(Condition::DateRange(DateRange {
+            from: rule.date_start,
+            to: rule.date_end,
+        }));
+    }
+    let amount = amount_condition(
+        rule.amount_min,
+        rule.amount_max,
+        rule.amount_exact,
+        rule.amount_tolerance,
+        rule.amount_sign.as_deref(),
+    );
+    if !amount.is_empty() {
+        filters.push(Condition::Amount(amount));
+    }
+    if let Some(json) = &rule.conditions {
+        filters.push(
+            serde_json::from_str(json)
+                .map_err(|e| format!("Condizioni non valide nella regola {}: {}", rule.id, e))?,
+        );
+    }
+    Ok(filters)
+}
+
+/// The whole condition of a rule. One of the description patterns must
+/// match, then the date window, the amount fields and the condition tree,
+/// when the rule has them, must match as well. A rule without any condition
+/// matches nothing.
+pub fn rule_condition(rule: &rule::Model) -> Result<Condition, String> {
+    let patterns: Vec<Condition> = rule
+        .patterns
+        .0
+        .iter()
+        .map(|pattern| {
+            Condition::Description(pattern_source(pattern, rule.patterns_case_insensitive))
+        })
+        .collect();
+
+    let mut all = rule_filters(rule)?;
+    if !patterns.is_empty() {
+        all.insert(0, Condition::Or(patterns));
+    }
+
+    if all.is_empty() {
+        return Ok(Condition::Or(Vec::new()));
+    }
+    Ok(Condition::And(all))
+}
+
+/// Rules activated on the account through `account_rules`.
+pub async fn get_active_rules(
+    db: &DatabaseConnection,
+    account_id: i32,
+) -> Result<Vec<rule::Model>, sea_orm::DbErr> {
+    Ok(account::Entity::find_by_id(account_id)
+        .find_with_related(rule::Entity)
+        .all(db)
+        .await?
+        .into_iter()
+        .flat_map(|(_acc, rules)| rules)
+        .collect())
+}
 
-        if !rule.date_start.is_none() && !rule.date_end.is_none() {
-            let date_start: Option<NaiveDateTime> =
-                rule.date_start.and_then(|d| d.and_hms_opt(0, 0, 0));
+struct CompiledRule {
+    rule: rule::Model,
+    /// Whether the rule has description patterns, one of them must match.
+    has_patterns: bool,
+    filters: CompiledCondition,
+}
+
+/// Rules ready to be matched against many transactions, as `rule_condition`
+/// describes them. The description patterns of all the rules are compiled
+/// once, in a single `RegexSet` searched once per transaction.
+pub struct RuleMatcher {
+    patterns: RegexSet,
+    /// Position in `rules` of the rule owning each pattern of the set.
+    pattern_rules: Vec<usize>,
+    rules: Vec<CompiledRule>,
+}
 
-            let date_end: Option<NaiveDateTime> =
-                rule.date_end.and_then(|d| d.and_hms_opt(23, 59, 59));
+impl RuleMatcher {
+    /// Rules that can't be compiled, saved before their patterns were
+    /// validated, are left out.
+    pub fn new(rules: Vec<rule::Model>) -> Result<Self, regex::Error> {
+        let mut sources = Vec::new();
+        let mut pattern_rules = Vec::new();
+        let mut compiled = Vec::new();
 
-            if (date_start.map_or(true, |start| transaction.date >= start))
-                && (date_end.map_or(true, |end| transaction.date <= end))
-            {
-                appliers.push(rule.clone());
+        for rule in rules {
+            let rule_sources: Vec<String> = rule
+                .patterns
+                .0
+                .iter()
+                .map(|pattern| pattern_source(pattern, rule.patterns_case_insensitive))
+                .collect();
+            if let Some(e) = rule_sources.iter().find_map(|s| Regex::new(s).err()) {
+                eprintln!("Regola {} ignorata: {}", rule.id, e);
+                continue;
             }
+            let filters = match rule_filters(&rule) {
+                Ok(filters) => filters,
+                Err(e) => {
+                    eprintln!("Regola {} ignorata: {}", rule.id, e);
+                    continue;
+                }
+            };
+            let has_patterns = !rule_sources.is_empty();
+            if !has_patterns && filters.is_empty() {
+                continue;
+            }
+            let filters = match Condition::And(filters).compile() {
+                Ok(filters) => filters,
+                Err(e) => {
+                    eprintln!("Regola {} ignorata: {}", rule.id, e);
+                    continue;
+                }
+            };
+
+            pattern_rules.extend(std::iter::repeat_n(compiled.len(), rule_sources.len()));
+            sources.extend(rule_sources);
+            compiled.push(CompiledRule {
+                rule,
+                has_patterns,
+                filters,
+            });
         }
+
+        Ok(RuleMatcher {
+            patterns: RegexSet::new(sources)?,
+            pattern_rules,
+            rules: compiled,
+        })
+    }
+
+    /// Rules matching the transaction.
+    pub fn matching(&self, transaction: &transaction::Model) -> Vec<rule::Model> {
+        let facts = Facts {
+            description: &transaction.description,
+            value: transaction.value,
+            date: transaction.date.date(),
+            label: &transaction.label,
+        };
+        let mut pattern_hits = vec![false; self.rules.len()];
+        for pattern in self.patterns.matches(&transaction.description).iter() {
+            pattern_hits[self.pattern_rules[pattern]] = true;
+        }
+
+        self.rules
+            .iter()
+            .zip(pattern_hits)
+            .filter(|(compiled, pattern_hit)| {
+                (!compiled.has_patterns || *pattern_hit) && compiled.filters.matches(&facts)
+            })
+            .map(|(compiled, _)| compiled.rule.clone())
+            .collect()
+    }
+}
+
+/// The transaction with category, label and percentage set by the rule.
+pub fn categorize_with_rule(
+    transaction: transaction::Model,
+    rule: &rule::Model,
+) -> transaction::ActiveModel {
+    let mut the_transaction: transaction::ActiveModel = transaction.into();
+    the_transaction.label = Set(rule.label.clone());
+    the_transaction.perc_to_exclude = Set(rule.percentage);
+    the_transaction.category_id = Set(Some(rule.category_id));
+    the_transaction
+}
+
+/// Conflict policy of the account, `ask` when it has no settings yet.
+pub async fn get_conflict_policy<C: ConnectionTrait>(
+    db: &C,
+    account_id: i32,
+) -> Result<String, sea_orm::DbErr> {
+    Ok(settings::Entity::find()
+        .filter(settings::Column::AccountId.eq(account_id))
+        .one(db)
+        .await?
+        .map(|s| s.rule_conflict_policy)
+        .unwrap_or_else(|| settings::CONFLICT_ASK.to_string()))
+}
+
+/// The rule applied to a transaction, among the ones matching it.
+pub enum RuleChoice {
+    NoMatch,
+    Winner {
+        rule: Box<rule::Model>,
+        reason: String,
+    },
+    /// Rules the conflict policy can't tell apart, left for the user.
+    Conflict(Vec<rule::Model>),
+}
+
+/// Keeps the rules with the highest `key`.
+fn best_rules(rules: Vec<rule::Model>, key: impl Fn(&rule::Model) -> i64) -> Vec<rule::Model> {
+    let best = rules.iter().map(&key).max().unwrap_or_default();
+    rules.into_iter().filter(|r| key(r) == best).collect()
+}
+
+fn specificity(rule: &rule::Model) -> i64 {
+    rule_condition(rule)
+        .map(|c| c.specificity() as i64)
+        .unwrap_or_default()
+}
+
+/// Settles a transaction matched by several rules according to the account
+/// conflict policy.
+pub fn choose_rule(mut matching: Vec<rule::Model>, policy: &str) -> RuleChoice {
+    let matched = matching.len();
+    if matched == 0 {
+        return RuleChoice::NoMatch;
+    }
+    if matched == 1 {
+        return RuleChoice::Winner {
+            rule: Box::new(matching.remove(0)),
+            reason: "Unica regola applicabile".to_string(),
+        };
+    }
+
+    let mut reason = String::new();
+    if policy == settings::CONFLICT_SPECIFIC {
+        matching = best_rules(matching, specificity);
+        reason = format!(
+            "Regola più specifica ({} condizioni) tra {} applicabili",
+            specificity(&matching[0]),
+            matched
+        );
+        if matching.len() > 1 {
+            matching = best_rules(matching, |r| r.priority.into());
+            reason = format!(
+                "Priorità più alta ({}) tra le regole più specifiche",
+                matching[0].priority
+            );
+        }
+    } else if policy == settings::CONFLICT_PRIORITY {
+        matching = best_rules(matching, |r| r.priority.into());
+        reason = format!(
+            "Priorità più alta ({}) tra {} applicabili",
+            matching[0].priority, matched
+        );
+    }
+
+    if matching.len() == 1 {
+        RuleChoice::Winner {
+            rule: Box::new(matching.remove(0)),
+            reason,
+        }
+    } else {
+        RuleChoice::Conflict(matching)
     }
+}
 
-    return appliers;
+/// What the account rules did with a transaction that just entered the
+/// ledger.
+pub enum RuleOutcome {
+    Categorized,
+    NoMatch,
+    /// Matched by rules the conflict policy can't tell apart, left for the
+    /// user.
+    Conflict,
+}
+
+/// Same matching as the rules page: conflicts the account policy can't
+/// settle wait for the user.
+pub async fn categorize_new_transaction<C: ConnectionTrait>(
+    db: &C,
+    transaction: transaction::Model,
+    rules: &RuleMatcher,
+    policy: &str,
+) -> Result<RuleOutcome, sea_orm::DbErr> {
+    let applicable_rules = rules.matching(&transaction);
+    match choose_rule(applicable_rules, policy) {
+        RuleChoice::NoMatch => Ok(RuleOutcome::NoMatch),
+        RuleChoice::Winner { rule, .. } => {
+            categorize_with_rule(transaction, &rule).update(db).await?;
+            Ok(RuleOutcome::Categorized)
+        }
+        RuleChoice::Conflict(_) => Ok(RuleOutcome::Conflict),
+    }
 }
 
 pub async fn preview_apply_rules(
     Path(account_id): Path<i32>,
     Extension(db): Extension<DatabaseConnection>,
-) -> Json<Vec<PreviewTransaction>> {
+) -> Result<Json<Vec<PreviewTransaction>>, StatusCode> {
     let mut previews: Vec<PreviewTransaction> = Vec::new();
 
     let uncategorized_transactions = transaction::Entity::find()
@@ -274,19 +757,39 @@ pub async fn preview_apply_rules(
         .into_iter()
         .flat_map(|(_acc, rules)| rules)
         .collect();
+    let matcher = RuleMatcher::new(active_rules).map_err(|e| {
+        eprintln!("Errore nella compilazione delle regole: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let policy = get_conflict_policy(&db, account_id).await.map_err(|e| {
+        eprintln!("Errore nel recupero della politica dei conflitti: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
 
     for transaction in uncategorized_transactions {
-        let applicable_rules = get_applayable_rules(transaction.clone(), active_rules.clone());
+        let applicable_rules = matcher.matching(&transaction);
         let mut category_new_value: String = String::new();
         let mut category_old_value: String = String::new();
         let mut new_percentage: f32 = transaction.perc_to_exclude;
         let mut new_label: String = String::new();
 
-        if applicable_rules.len() == 0 {
-            continue;
-        } else if applicable_rules.len() == 1 {
-            let the_rule = &applicable_rules[0];
+        let (conflicts, winner, reason) = match choose_rule(applicable_rules, &policy) {
+            RuleChoice::NoMatch => continue,
+            RuleChoice::Winner { rule, reason } => (vec![(*rule).clone()], Some(*rule), reason),
+            RuleChoice::Conflict(rules) if policy == settings::CONFLICT_ASK => (
+                rules,
+                None,
+                "Più regole applicabili, scegli quale applicare".to_string(),
+            ),
+            RuleChoice::Conflict(rules) => (
+                rules,
+                None,
+                "Più regole applicabili a pari merito, scegli quale applicare".to_string(),
+            ),
+        };
 
+        if let Some(the_rule) = &winner {
             category_old_value = match transaction.category_id {
                 Some(cat_id) => category::Entity::find_by_id(cat_id)
                     .one(&db)
@@ -316,7 +819,9 @@ pub async fn preview_apply_rules(
             description: transaction.description,
             value: transaction.value,
             date: transaction.date.to_string(),
-            conflicts: applicable_rules,
+            conflicts,
+            winning_rule: winner.map(|r| r.name),
+            reason,
             label_old_value: transaction.label,
             label_new_value: new_label,
             perc_to_exclude_old_value: transaction.perc_to_exclude,
@@ -326,7 +831,7 @@ pub async fn preview_apply_rules(
         });
     }
 
-    Json(previews)
+    Ok(Json(previews))
 }
 
 pub async fn apply_rules(
@@ -350,16 +855,21 @@ pub async fn apply_rules(
         .into_iter()
         .flat_map(|(_acc, rules)| rules)
         .collect();
+    let matcher = RuleMatcher::new(active_rules).map_err(|e| {
+        eprintln!("Errore nella compilazione delle regole: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let policy = get_conflict_policy(&db, account_id).await.map_err(|e| {
+        eprintln!("Errore nel recupero della politica dei conflitti: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
 
     for transaction in uncategorized_transactions {
-        let applicable_rules = get_applayable_rules(transaction.clone(), active_rules.clone());
+        let applicable_rules = matcher.matching(&transaction);
 
-        if applicable_rules.len() == 1 {
-            let the_rule = &applicable_rules[0];
-            let mut the_transaction: transaction::ActiveModel = transaction.into();
-            the_transaction.label = Set(the_rule.label.clone());
-            the_transaction.perc_to_exclude = Set(the_rule.percentage);
-            the_transaction.category_id = Set(Some(the_rule.category_id));
+        if let RuleChoice::Winner { rule, .. } = choose_rule(applicable_rules, &policy) {
+            let the_transaction = categorize_with_rule(transaction, &rule);
 
             the_transaction.update(&db).await.map_err(|err| {
                 eprint!("Cannot update transaction: {}", err);
@@ -386,6 +896,13 @@ pub async fn resolve_conflicts_rules(
         .into_iter()
         .flat_map(|(_acc, rules)| rules)
         .collect();
+    let matcher = match RuleMatcher::new(active_rules) {
+        Ok(matcher) => matcher,
+        Err(e) => {
+            eprintln!("Errore nella compilazione delle regole: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    };
 
     for item in payload {
         let transaction = transaction::Entity::find_by_id(item.transaction_id)
@@ -393,7 +910,7 @@ pub async fn resolve_conflicts_rules(
             .await
             .expect("Error reading transaction!")[0]
             .clone();
-        let applicable_rules = get_applayable_rules(transaction.clone(), active_rules.clone());
+        let applicable_rules = matcher.matching(&transaction);
 
         if applicable_rules.len() <= 1 || !applicable_rules.iter().any(|r| r.id == item.rule_id) {
             return StatusCode::NOT_FOUND;
@@ -418,3 +935,162 @@ pub async fn resolve_conflicts_rules(
 
     return StatusCode::OK;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(id: i32, patterns: &[&str], priority: i32) -> rule::Model {
+        rule::Model {
+            id,
+            name: format!("Regola {}", id),
+            label: String::new(),
+            percentage: 0.0,
+            category_id: 1,
+            patterns: rule::Patterns(patterns.iter().map(|p| p.to_string()).collect()),
+            patterns_case_insensitive: false,
+            date_start: None,
+            date_end: None,
+            amount_min: None,
+            amount_max: None,
+            amount_exact: None,
+            amount_tolerance: None,
+            amount_sign: None,
+            conditions: None,
+            priority,
+        }
+    }
+
+    fn transaction(day: &str, value: f64, description: &str) -> transaction::Model {
+        transaction::Model {
+            id: 1,
+            account_id: 1,
+            category_id: None,
+            value,
+            description: description.to_string(),
+            date: NaiveDate::parse_from_str(day, "%Y-%m-%d")
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap(),
+            perc_to_exclude: 0.0,
+            label: String::new(),
+            external_id: None,
+            import_batch_id: None,
+        }
+    }
+
+    fn winner(choice: RuleChoice) -> i32 {
+        match choice {
+            RuleChoice::Winner { rule, .. } => rule.id,
+            RuleChoice::NoMatch => panic!("nessuna regola scelta"),
+            RuleChoice::Conflict(rules) => panic!("conflitto tra {} regole", rules.len()),
+        }
+    }
+
+    fn conflict(choice: RuleChoice) -> Vec<i32> {
+        match choice {
+            RuleChoice::Conflict(rules) => rules.iter().map(|r| r.id).collect(),
+            _ => panic!("nessun conflitto"),
+        }
+    }
+
+    #[test]
+    fn choose_rule_by_policy() {
+        assert!(matches!(
+            choose_rule(Vec::new(), settings::CONFLICT_PRIORITY),
+            RuleChoice::NoMatch
+        ));
+        assert_eq!(
+            winner(choose_rule(
+                vec![rule(1, &["BAR"], 0)],
+                settings::CONFLICT_ASK
+            )),
+            1
+        );
+
+        let broad = rule(1, &["AMAZON"], 5);
+        let mut narrow = rule(2, &["AMAZON"], 1);
+        narrow.amount_sign = Some(rule::SIGN_EXPENSE.to_string());
+        let matching = vec![broad, narrow];
+
+        // Priority ignores how many conditions the rules have.
+        assert_eq!(
+            winner(choose_rule(matching.clone(), settings::CONFLICT_PRIORITY)),
+            1
+        );
+        // The most specific rule wins over a higher priority.
+        assert_eq!(
+            winner(choose_rule(matching.clone(), settings::CONFLICT_SPECIFIC)),
+            2
+        );
+        assert_eq!(
+            conflict(choose_rule(matching, settings::CONFLICT_ASK)),
+            vec![1, 2]
+        );
+    }
+
+    #[test]
+    fn choose_rule_ties() {
+        // Equally specific rules fall back to their priority.
+        let matching = vec![rule(1, &["AMAZON"], 1), rule(2, &["PRIME"], 3)];
+        assert_eq!(
+            winner(choose_rule(matching, settings::CONFLICT_SPECIFIC)),
+            2
+        );
+
+        // Same priority, the user decides.
+        let matching = vec![
+            rule(1, &["AMAZON"], 2),
+            rule(2, &["PRIME"], 2),
+            rule(3, &["MARKETPLACE"], 1),
+        ];
+        assert_eq!(
+            conflict(choose_rule(matching.clone(), settings::CONFLICT_PRIORITY)),
+            vec![1, 2]
+        );
+        assert_eq!(
+            conflict(choose_rule(matching, settings::CONFLICT_SPECIFIC)),
+            vec![1, 2]
+        );
+    }
+
+    #[test]
+    fn rule_matcher() {
+        let mut insensitive = rule(2, &["esselunga"], 0);
+        insensitive.patterns_case_insensitive = true;
+        let mut windowed = rule(3, &["BAR", "CAFFE"], 0);
+        windowed.date_start = NaiveDate::from_ymd_opt(2025, 1, 1);
+        windowed.date_end = NaiveDate::from_ymd_opt(2025, 1, 31);
+        let mut amount_only = rule(4, &[], 0);
+        amount_only.amount_min = Some(1000.0);
+        amount_only.amount_sign = Some(rule::SIGN_INCOME.to_string());
+
+        let matcher = RuleMatcher::new(vec![
+            rule(1, &["ESSELUNGA"], 0),
+            insensitive,
+            windowed,
+            amount_only,
+            // Saved before patterns were validated: left out, not an error.
+            rule(5, &["(AMAZON"], 0),
+            // No condition at all matches nothing.
+            rule(6, &[], 0),
+        ])
+        .unwrap();
+        let ids = |day, value, description| -> Vec<i32> {
+            matcher
+                .matching(&transaction(day, value, description))
+                .iter()
+                .map(|r| r.id)
+                .collect()
+        };
+
+        assert_eq!(ids("2025-01-10", -40.0, "POS ESSELUNGA MILANO"), vec![1, 2]);
+        assert_eq!(ids("2025-01-10", -40.0, "Pos Esselunga Milano"), vec![2]);
+        // Any pattern of the rule, but only inside its date window.
+        assert_eq!(ids("2025-01-10", -2.5, "CAFFE CENTRALE"), vec![3]);
+        assert!(ids("2025-02-10", -2.5, "BAR CENTRALE").is_empty());
+        assert_eq!(ids("2025-02-10", 1500.0, "STIPENDIO"), vec![4]);
+        assert!(ids("2025-02-10", -1500.0, "AFFITTO").is_empty());
+        assert!(ids("2025-01-10", -30.0, "(AMAZON").is_empty());
+    }
+}
@@ -2,26 +2,35 @@ use askama::Template;
 use axum::{extract::Path, http::StatusCode, response::Redirect, Extension, Form};
 use sea_orm::{
     ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, ModelTrait,
-    QueryFilter,
+    QueryFilter, QueryOrder,
 };
 
-use crate::database::entities::{account, settings};
+use crate::{
+    database::entities::{account, import_profile, settings},
+    routes::account_transactions::empty_string_as_none,
+};
 
 #[derive(Template)]
 #[template(path = "account_settings.html")]
 struct SettingsTemplate<'a> {
     account: account::Model,
     settings: settings::Model,
+    profiles: Vec<import_profile::Model>,
     menu: &'a str,
     sub_menu: &'a str,
 }
 
 #[derive(serde::Deserialize)]
 pub struct UpdateSettingForm {
-    date_index: i32,
-    description_index: i32,
-    value_index: i32,
-    starter_string: String,
+    #[serde(deserialize_with = "empty_string_as_none")]
+    profile_id: Option<i32>,
+    /// Checkbox, only sent when checked.
+    auto_apply_rules: Option<String>,
+    #[serde(deserialize_with = "empty_string_as_none")]
+    verified_balance: Option<f64>,
+    #[serde(deserialize_with = "empty_string_as_none")]
+    verified_balance_date: Option<chrono::NaiveDate>,
+    rule_conflict_policy: String,
 }
 
 pub async fn get_account_setting_handler(
@@ -37,15 +46,14 @@ pub async fn get_account_setting_handler(
         })?
         .ok_or(StatusCode::NOT_FOUND)?;
 
-    let settings: settings::Model = match account_data.find_related(settings::Entity).one(&db).await {
+    let settings: settings::Model = match account_data.find_related(settings::Entity).one(&db).await
+    {
         Ok(Some(s)) => s,
         Ok(None) => {
             let new_setting = settings::ActiveModel {
                 account_id: Set(account_data.id),
-                date_index: Set(0),
-                description_index: Set(0),
-                value_index: Set(0),
-                starter_string: Set("".to_string()),
+                profile_id: Set(None),
+                auto_apply_rules: Set(false),
                 ..Default::default()
             };
 
@@ -62,9 +70,19 @@ pub async fn get_account_setting_handler(
         }
     };
 
+    let profiles = import_profile::Entity::find()
+        .order_by_asc(import_profile::Column::Name)
+        .all(&db)
+        .await
+        .map_err(|e| {
+            eprintln!("Errore recupero profili di import: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
     let html = SettingsTemplate {
         account: account_data,
         settings,
+        profiles,
         menu: "accounts",
         sub_menu: "settings",
     };
@@ -87,14 +105,29 @@ pub async fn update_setting_handler(
         })?
         .ok_or(StatusCode::NOT_FOUND)?;
 
+    if ![
+        settings::CONFLICT_PRIORITY,
+        settings::CONFLICT_SPECIFIC,
+        settings::CONFLICT_ASK,
+    ]
+    .contains(&form.rule_conflict_policy.as_str())
+    {
+        eprintln!(
+            "Politica dei conflitti non valida: '{}'",
+            form.rule_conflict_policy
+        );
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
     let mut the_settings: settings::ActiveModel = settings.into();
-    the_settings.date_index = Set(form.date_index);
-    the_settings.description_index = Set(form.description_index);
-    the_settings.value_index = Set(form.value_index);
-    the_settings.starter_string = Set(form.starter_string);
+    the_settings.profile_id = Set(form.profile_id);
+    the_settings.auto_apply_rules = Set(form.auto_apply_rules.is_some());
+    the_settings.verified_balance = Set(form.verified_balance);
+    the_settings.verified_balance_date = Set(form.verified_balance_date);
+    the_settings.rule_conflict_policy = Set(form.rule_conflict_policy);
     the_settings.update(&db).await.map_err(|err| {
         println!("Cannot update settings: {}", err);
-        StatusCode::INTERNAL_SERVER_ERROR
+        StatusCode::BAD_REQUEST
     })?;
 
     Ok(Redirect::to(&format!("/accounts/{}/settings", account_id)))
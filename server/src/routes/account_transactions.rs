@@ -42,16 +42,21 @@ pub struct AddTransactionForm {
     category_id: Option<i32>,
 }
 
-fn empty_string_as_none<'de, D>(deserializer: D) -> Result<Option<i32>, D::Error>
+pub fn empty_string_as_none<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
 where
     D: serde::Deserializer<'de>,
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
 {
     let opt = Option::<String>::deserialize(deserializer)?;
     if let Some(s) = opt {
         if s.trim().is_empty() {
             Ok(None)
         } else {
-            s.parse::<i32>().map(Some).map_err(serde::de::Error::custom)
+            s.trim()
+                .parse::<T>()
+                .map(Some)
+                .map_err(serde::de::Error::custom)
         }
     } else {
         Ok(None)
@@ -0,0 +1,279 @@
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{NaiveDate, NaiveDateTime};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::{account, settings, transaction},
+    routes::{
+        account_rules::{categorize_new_transaction, get_active_rules, RuleMatcher, RuleOutcome},
+        uploader::{load_category_lookup, DuplicateIndex, DuplicateStatus, TransactionData},
+    },
+};
+
+/// A transaction sent by a script, same fields as the transaction form.
+#[derive(Deserialize)]
+pub struct BatchTransaction {
+    /// `YYYY-MM-DD`, optionally followed by a `THH:MM[:SS]` time.
+    date: String,
+    value: f64,
+    description: String,
+    /// `macro_category:category`, as in the statement files.
+    #[serde(default)]
+    category: Option<String>,
+    #[serde(default)]
+    label: String,
+    /// Share of the amount left out of the reports, from 0 to 1.
+    #[serde(default)]
+    perc_to_exclude: f32,
+    #[serde(default)]
+    external_id: Option<String>,
+}
+
+#[derive(Serialize)]
+struct InvalidTransaction {
+    index: usize,
+    field: &'static str,
+    reason: String,
+}
+
+#[derive(Serialize)]
+struct RejectedBatch {
+    message: String,
+    errors: Vec<InvalidTransaction>,
+}
+
+/// A transaction inserted although it looks like one already stored.
+#[derive(Serialize)]
+struct NearDuplicate {
+    index: usize,
+    transaction_id: i32,
+    existing_transaction_id: i32,
+}
+
+#[derive(Serialize)]
+struct BatchSummary {
+    /// Ids of the new transactions, in the order they were sent.
+    transaction_ids: Vec<i32>,
+    /// Positions of the transactions already stored, not inserted again.
+    duplicates_skipped: Vec<usize>,
+    near_duplicates: Vec<NearDuplicate>,
+    auto_categorized: usize,
+    uncategorized: usize,
+    rule_conflicts: usize,
+}
+
+fn parse_date_time(raw: &str) -> Option<NaiveDateTime> {
+    let raw = raw.trim();
+
+    ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(raw, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        })
+}
+
+/// Inserts a list of transactions for scripts feeding sources the uploader
+/// doesn't read. Every transaction is validated before anything is written,
+/// then all of them are inserted in one database transaction. Duplicates are
+/// detected as for statement files and the account rules are applied when
+/// the account settings ask for it on imports.
+pub async fn add_transactions_batch_handler(
+    Path(account_id): Path<i32>,
+    Extension(db): Extension<DatabaseConnection>,
+    Json(batch): Json<Vec<BatchTransaction>>,
+) -> impl IntoResponse {
+    match account::Entity::find_by_id(account_id).one(&db).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Errore nel recupero account: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+
+    let mut errors = Vec::new();
+    let mut rows = Vec::new();
+    let mut transactions = Vec::new();
+    for (index, item) in batch.into_iter().enumerate() {
+        let mut invalid = |field, reason: String| {
+            errors.push(InvalidTransaction {
+                index,
+                field,
+                reason,
+            })
+        };
+
+        let date = parse_date_time(&item.date);
+        if date.is_none() {
+            invalid("date", format!("Data non valida '{}'", item.date));
+        }
+        if item.description.trim().is_empty() {
+            invalid("description", "Descrizione mancante".to_string());
+        }
+        if !item.value.is_finite() {
+            invalid("value", "Importo non valido".to_string());
+        }
+        if !(0.0..=1.0).contains(&item.perc_to_exclude) {
+            invalid(
+                "perc_to_exclude",
+                "La percentuale da escludere deve essere una frazione tra 0 e 1".to_string(),
+            );
+        }
+
+        if let Some(date) = date {
+            rows.push((index, date, item.perc_to_exclude));
+            transactions.push(TransactionData {
+                description: item.description.trim().to_string(),
+                value: item.value,
+                date: date.date(),
+                external_id: item.external_id.filter(|id| !id.trim().is_empty()),
+                label: Some(item.label),
+                category: item.category.filter(|c| !c.trim().is_empty()),
+                ..Default::default()
+            });
+        }
+    }
+
+    let categories = match load_category_lookup(&db, &transactions).await {
+        Ok(categories) => categories,
+        Err(e) => {
+            eprintln!("Errore nel recupero delle categorie: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    for ((index, ..), transaction) in rows.iter().zip(&transactions) {
+        if let Some(category) = &transaction.category {
+            if !categories.contains_key(&category.to_lowercase()) {
+                errors.push(InvalidTransaction {
+                    index: *index,
+                    field: "category",
+                    reason: format!("Categoria sconosciuta '{}'", category),
+                });
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        errors.sort_by_key(|e| e.index);
+        return (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(RejectedBatch {
+                message: format!("Nessuna transazione inserita: {} errori", errors.len()),
+                errors,
+            }),
+        )
+            .into_response();
+    }
+
+    let result: Result<BatchSummary, sea_orm::DbErr> = async {
+        let account_settings = settings::Entity::find()
+            .filter(settings::Column::AccountId.eq(account_id))
+            .one(&db)
+            .await?;
+        let auto_apply_rules = account_settings
+            .as_ref()
+            .is_some_and(|s| s.auto_apply_rules);
+        let policy = account_settings
+            .map(|s| s.rule_conflict_policy)
+            .unwrap_or_else(|| settings::CONFLICT_ASK.to_string());
+        let rules = if auto_apply_rules {
+            get_active_rules(&db, account_id).await?
+        } else {
+            Vec::new()
+        };
+        let rules = RuleMatcher::new(rules).map_err(|e| sea_orm::DbErr::Custom(e.to_string()))?;
+
+        let txn = db.begin().await?;
+
+        let mut duplicates = DuplicateIndex::load(&txn, account_id, &transactions).await?;
+
+        let mut summary = BatchSummary {
+            transaction_ids: Vec::new(),
+            duplicates_skipped: Vec::new(),
+            near_duplicates: Vec::new(),
+            auto_categorized: 0,
+            uncategorized: 0,
+            rule_conflicts: 0,
+        };
+
+        for ((index, date, perc_to_exclude), data) in rows.into_iter().zip(transactions) {
+            let duplicate_of = match duplicates.check(&data) {
+                DuplicateStatus::Duplicate => {
+                    summary.duplicates_skipped.push(index);
+                    continue;
+                }
+                DuplicateStatus::NearDuplicate(existing_transaction_id) => {
+                    Some(existing_transaction_id)
+                }
+                DuplicateStatus::Unique => None,
+            };
+
+            let category_id = data
+                .category
+                .and_then(|c| categories.get(&c.to_lowercase()).copied());
+            let inserted = transaction::ActiveModel {
+                account_id: Set(account_id),
+                category_id: Set(category_id),
+                value: Set(data.value),
+                description: Set(data.description),
+                date: Set(date),
+                perc_to_exclude: Set(perc_to_exclude),
+                label: Set(data.label.unwrap_or_default()),
+                external_id: Set(data.external_id),
+                ..Default::default()
+            }
+            .insert(&txn)
+            .await?;
+
+            summary.transaction_ids.push(inserted.id);
+            if let Some(existing_transaction_id) = duplicate_of {
+                summary.near_duplicates.push(NearDuplicate {
+                    index,
+                    transaction_id: inserted.id,
+                    existing_transaction_id,
+                });
+            }
+
+            if inserted.category_id.is_some() {
+                continue;
+            }
+            match categorize_new_transaction(&txn, inserted, &rules, &policy).await? {
+                RuleOutcome::Categorized => summary.auto_categorized += 1,
+                RuleOutcome::NoMatch => summary.uncategorized += 1,
+                RuleOutcome::Conflict => {
+                    summary.uncategorized += 1;
+                    summary.rule_conflicts += 1;
+                }
+            }
+        }
+
+        txn.commit().await?;
+        Ok(summary)
+    }
+    .await;
+
+    match result {
+        Ok(summary) => (StatusCode::OK, Json(summary)).into_response(),
+        Err(e) => {
+            eprintln!("Errore inserimento transazioni: {:?}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Errore inserimento transazioni",
+            )
+                .into_response()
+        }
+    }
+}
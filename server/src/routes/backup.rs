@@ -1,9 +1,11 @@
 use axum::http::StatusCode;
 use chrono::{DateTime, NaiveDate, Utc};
-use sea_orm::{DatabaseConnection, EntityTrait};
+use sea_orm::{ActiveValue::Set, DatabaseConnection, EntityTrait};
 use serde::{Deserialize, Serialize};
 
-use crate::database::{account, account_rule, budget, category, rule, settings, transaction};
+use crate::database::{
+    account, account_rule, budget, category, import_profile, rule, settings, transaction,
+};
 
 #[derive(Serialize, Deserialize)]
 pub struct FullBackupDTO {
@@ -14,6 +16,8 @@ pub struct FullBackupDTO {
     pub categories: Vec<CategoryDTO>,
     pub account_rules: Vec<AccountRuleDTO>,
     pub settings: Vec<AccountSettingsDTO>,
+    #[serde(default)]
+    pub import_profiles: Vec<ImportProfileDTO>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -40,6 +44,8 @@ pub struct TransactionDTO {
     pub date: DateTime<chrono::Utc>,
     pub perc_to_exclude: f32,
     pub label: String,
+    #[serde(default)]
+    pub external_id: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -49,9 +55,29 @@ pub struct RuleDTO {
     pub label: String,
     pub percentage: f32,
     pub category_id: i32,
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    #[serde(default)]
+    pub patterns_case_insensitive: bool,
+    /// Patterns of backups taken when they were a comma separated string.
+    #[serde(default, skip_serializing)]
     pub regexpr: Option<String>,
     pub date_start: Option<NaiveDate>,
     pub date_end: Option<NaiveDate>,
+    #[serde(default)]
+    pub amount_min: Option<f64>,
+    #[serde(default)]
+    pub amount_max: Option<f64>,
+    #[serde(default)]
+    pub amount_exact: Option<f64>,
+    #[serde(default)]
+    pub amount_tolerance: Option<f64>,
+    #[serde(default)]
+    pub amount_sign: Option<String>,
+    #[serde(default)]
+    pub conditions: Option<String>,
+    #[serde(default)]
+    pub priority: i32,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -73,10 +99,248 @@ pub struct AccountRuleDTO {
 pub struct AccountSettingsDTO {
     pub id: i32,
     pub account_id: i32,
+    #[serde(default)]
+    pub profile_id: Option<i32>,
+    #[serde(default)]
+    pub auto_apply_rules: bool,
+    #[serde(default)]
+    pub verified_balance: Option<f64>,
+    #[serde(default)]
+    pub verified_balance_date: Option<NaiveDate>,
+    #[serde(default = "default_rule_conflict_policy")]
+    pub rule_conflict_policy: String,
+    /// Backups taken before import profiles existed carry the mapping in the
+    /// settings, restore turns it into a profile for the account.
+    #[serde(flatten, skip_serializing)]
+    pub legacy_mapping: ImportMappingDTO,
+}
+
+/// Also the format of the JSON profile export.
+#[derive(Serialize, Deserialize)]
+pub struct ImportProfileDTO {
+    #[serde(default)]
+    pub id: i32,
+    pub name: String,
+    #[serde(default)]
+    pub builtin: bool,
+    #[serde(default = "default_format")]
+    pub format: String,
+    #[serde(flatten)]
+    pub mapping: ImportMappingDTO,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ImportMappingDTO {
+    #[serde(default)]
+    pub starter_string: String,
+    #[serde(default)]
     pub date_index: i32,
+    #[serde(default)]
     pub description_index: i32,
+    #[serde(default)]
     pub value_index: i32,
-    pub starter_string: String,
+    #[serde(default = "default_delimiter")]
+    pub delimiter: String,
+    #[serde(default = "default_quote_char")]
+    pub quote_char: String,
+    #[serde(default = "default_encoding")]
+    pub encoding: String,
+    #[serde(default = "default_decimal_separator")]
+    pub decimal_separator: String,
+    #[serde(default = "default_thousands_separator")]
+    pub thousands_separator: String,
+    #[serde(default = "default_date_format")]
+    pub date_format: String,
+    #[serde(default)]
+    pub date_header: String,
+    #[serde(default)]
+    pub description_header: String,
+    #[serde(default)]
+    pub value_header: String,
+    #[serde(default)]
+    pub currency_header: String,
+    #[serde(default)]
+    pub currency_index: Option<i32>,
+    #[serde(default)]
+    pub counterparty_header: String,
+    #[serde(default)]
+    pub counterparty_index: Option<i32>,
+    #[serde(default)]
+    pub reference_header: String,
+    #[serde(default)]
+    pub reference_index: Option<i32>,
+    #[serde(default)]
+    pub balance_header: String,
+    #[serde(default)]
+    pub balance_index: Option<i32>,
+    #[serde(default = "default_amount_layout")]
+    pub amount_layout: String,
+    #[serde(default)]
+    pub debit_header: String,
+    #[serde(default)]
+    pub debit_index: Option<i32>,
+    #[serde(default)]
+    pub credit_header: String,
+    #[serde(default)]
+    pub credit_index: Option<i32>,
+    #[serde(default)]
+    pub direction_header: String,
+    #[serde(default)]
+    pub direction_index: Option<i32>,
+    #[serde(default = "default_debit_markers")]
+    pub debit_markers: String,
+    #[serde(default)]
+    pub invert_sign: bool,
+    #[serde(default)]
+    pub worksheet: String,
+    #[serde(default)]
+    pub end_marker: String,
+    #[serde(default)]
+    pub stop_at_blank_row: bool,
+    #[serde(default)]
+    pub skip_patterns: String,
+    #[serde(default)]
+    pub pdf_line_patterns: String,
+}
+
+fn default_rule_conflict_policy() -> String {
+    settings::CONFLICT_ASK.to_string()
+}
+
+// Backups taken before the CSV settings existed don't carry them, so restore
+// falls back to the same defaults used by the migration.
+fn default_delimiter() -> String {
+    ";".to_string()
+}
+
+fn default_quote_char() -> String {
+    "\"".to_string()
+}
+
+fn default_encoding() -> String {
+    "utf-8".to_string()
+}
+
+fn default_decimal_separator() -> String {
+    ",".to_string()
+}
+
+fn default_thousands_separator() -> String {
+    ".".to_string()
+}
+
+fn default_date_format() -> String {
+    "%d/%m/%Y".to_string()
+}
+
+fn default_amount_layout() -> String {
+    import_profile::AMOUNT_SIGNED.to_string()
+}
+
+fn default_format() -> String {
+    import_profile::FORMAT_AUTO.to_string()
+}
+
+fn default_debit_markers() -> String {
+    "D|Dare|Addebito|Uscita".to_string()
+}
+
+impl Default for ImportMappingDTO {
+    fn default() -> Self {
+        serde_json::from_str("{}").expect("every mapping field has a default")
+    }
+}
+
+pub fn import_profile_to_dto(profile: import_profile::Model) -> ImportProfileDTO {
+    ImportProfileDTO {
+        id: profile.id,
+        name: profile.name,
+        builtin: profile.builtin,
+        format: profile.format,
+        mapping: ImportMappingDTO {
+            starter_string: profile.starter_string,
+            date_index: profile.date_index,
+            description_index: profile.description_index,
+            value_index: profile.value_index,
+            delimiter: profile.delimiter,
+            quote_char: profile.quote_char,
+            encoding: profile.encoding,
+            decimal_separator: profile.decimal_separator,
+            thousands_separator: profile.thousands_separator,
+            date_format: profile.date_format,
+            date_header: profile.date_header,
+            description_header: profile.description_header,
+            value_header: profile.value_header,
+            currency_header: profile.currency_header,
+            currency_index: profile.currency_index,
+            counterparty_header: profile.counterparty_header,
+            counterparty_index: profile.counterparty_index,
+            reference_header: profile.reference_header,
+            reference_index: profile.reference_index,
+            balance_header: profile.balance_header,
+            balance_index: profile.balance_index,
+            amount_layout: profile.amount_layout,
+            debit_header: profile.debit_header,
+            debit_index: profile.debit_index,
+            credit_header: profile.credit_header,
+            credit_index: profile.credit_index,
+            direction_header: profile.direction_header,
+            direction_index: profile.direction_index,
+            debit_markers: profile.debit_markers,
+            invert_sign: profile.invert_sign,
+            worksheet: profile.worksheet,
+            end_marker: profile.end_marker,
+            stop_at_blank_row: profile.stop_at_blank_row,
+            skip_patterns: profile.skip_patterns,
+            pdf_line_patterns: profile.pdf_line_patterns,
+        },
+    }
+}
+
+/// The id is left for the caller to set, restore keeps it while the JSON
+/// profile import lets the database pick a new one.
+pub fn import_profile_from_dto(dto: ImportProfileDTO) -> import_profile::ActiveModel {
+    import_profile::ActiveModel {
+        name: Set(dto.name),
+        builtin: Set(dto.builtin),
+        format: Set(dto.format),
+        starter_string: Set(dto.mapping.starter_string),
+        date_index: Set(dto.mapping.date_index),
+        description_index: Set(dto.mapping.description_index),
+        value_index: Set(dto.mapping.value_index),
+        delimiter: Set(dto.mapping.delimiter),
+        quote_char: Set(dto.mapping.quote_char),
+        encoding: Set(dto.mapping.encoding),
+        decimal_separator: Set(dto.mapping.decimal_separator),
+        thousands_separator: Set(dto.mapping.thousands_separator),
+        date_format: Set(dto.mapping.date_format),
+        date_header: Set(dto.mapping.date_header),
+        description_header: Set(dto.mapping.description_header),
+        value_header: Set(dto.mapping.value_header),
+        currency_header: Set(dto.mapping.currency_header),
+        currency_index: Set(dto.mapping.currency_index),
+        counterparty_header: Set(dto.mapping.counterparty_header),
+        counterparty_index: Set(dto.mapping.counterparty_index),
+        reference_header: Set(dto.mapping.reference_header),
+        reference_index: Set(dto.mapping.reference_index),
+        balance_header: Set(dto.mapping.balance_header),
+        balance_index: Set(dto.mapping.balance_index),
+        amount_layout: Set(dto.mapping.amount_layout),
+        debit_header: Set(dto.mapping.debit_header),
+        debit_index: Set(dto.mapping.debit_index),
+        credit_header: Set(dto.mapping.credit_header),
+        credit_index: Set(dto.mapping.credit_index),
+        direction_header: Set(dto.mapping.direction_header),
+        direction_index: Set(dto.mapping.direction_index),
+        debit_markers: Set(dto.mapping.debit_markers),
+        invert_sign: Set(dto.mapping.invert_sign),
+        worksheet: Set(dto.mapping.worksheet),
+        end_marker: Set(dto.mapping.end_marker),
+        stop_at_blank_row: Set(dto.mapping.stop_at_blank_row),
+        skip_patterns: Set(dto.mapping.skip_patterns),
+        pdf_line_patterns: Set(dto.mapping.pdf_line_patterns),
+        ..Default::default()
+    }
 }
 
 pub async fn get_full_backup(db: &DatabaseConnection) -> Result<String, StatusCode> {
@@ -124,6 +388,7 @@ pub async fn get_full_backup(db: &DatabaseConnection) -> Result<String, StatusCo
             date: DateTime::from_naive_utc_and_offset(t.date, Utc),
             perc_to_exclude: t.perc_to_exclude,
             label: t.label,
+            external_id: t.external_id,
         })
         .collect();
 
@@ -140,9 +405,18 @@ pub async fn get_full_backup(db: &DatabaseConnection) -> Result<String, StatusCo
             label: r.label,
             percentage: r.percentage,
             category_id: r.category_id,
-            regexpr: r.regexpr,
+            patterns: r.patterns.0,
+            patterns_case_insensitive: r.patterns_case_insensitive,
+            regexpr: None,
             date_start: r.date_start,
             date_end: r.date_end,
+            amount_min: r.amount_min,
+            amount_max: r.amount_max,
+            amount_exact: r.amount_exact,
+            amount_tolerance: r.amount_tolerance,
+            amount_sign: r.amount_sign,
+            conditions: r.conditions,
+            priority: r.priority,
         })
         .collect();
 
@@ -185,13 +459,25 @@ pub async fn get_full_backup(db: &DatabaseConnection) -> Result<String, StatusCo
         .map(|account_setting| AccountSettingsDTO {
             id: account_setting.id,
             account_id: account_setting.account_id,
-            date_index: account_setting.date_index,
-            description_index: account_setting.description_index,
-            value_index: account_setting.value_index,
-            starter_string: account_setting.starter_string,
+            profile_id: account_setting.profile_id,
+            auto_apply_rules: account_setting.auto_apply_rules,
+            verified_balance: account_setting.verified_balance,
+            verified_balance_date: account_setting.verified_balance_date,
+            rule_conflict_policy: account_setting.rule_conflict_policy,
+            legacy_mapping: ImportMappingDTO::default(),
         })
         .collect();
 
+    let import_profiles_model = import_profile::Entity::find().all(db).await.map_err(|e| {
+        eprintln!("Errore recuperando import_profiles: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let import_profiles_dto: Vec<ImportProfileDTO> = import_profiles_model
+        .into_iter()
+        .map(import_profile_to_dto)
+        .collect();
+
     let backup = FullBackupDTO {
         accounts: accounts_dto,
         budgets: budgets_dto,
@@ -200,6 +486,7 @@ pub async fn get_full_backup(db: &DatabaseConnection) -> Result<String, StatusCo
         categories: categories_dto,
         account_rules: account_rules_dto,
         settings: settings_dto,
+        import_profiles: import_profiles_dto,
     };
 
     let json_backup = serde_json::to_string_pretty(&backup).map_err(|e| {
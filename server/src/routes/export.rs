@@ -0,0 +1,83 @@
+use std::fmt::Write;
+
+use axum::{
+    extract::{Extension, Path},
+    http::{header, Response, StatusCode},
+    response::IntoResponse,
+};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
+
+use crate::database::{account, category, transaction};
+
+/// QIF fields are line based, so values can't span multiple lines.
+fn qif_value(value: &str) -> String {
+    value.replace(['\r', '\n'], " ").trim().to_string()
+}
+
+fn write_qif(transactions: Vec<(transaction::Model, Option<category::Model>)>) -> String {
+    let mut qif = String::from("!Type:Bank\n");
+
+    for (transaction, category) in transactions {
+        let _ = writeln!(qif, "D{}", transaction.date.format("%m/%d/%Y"));
+        let _ = writeln!(qif, "T{:.2}", transaction.value);
+        let _ = writeln!(qif, "P{}", qif_value(&transaction.description));
+        if !transaction.label.trim().is_empty() {
+            let _ = writeln!(qif, "M{}", qif_value(&transaction.label));
+        }
+        if let Some(category) = category {
+            let _ = writeln!(
+                qif,
+                "L{}:{}",
+                qif_value(&category.macro_category),
+                qif_value(&category.category)
+            );
+        }
+        qif.push_str("^\n");
+    }
+
+    qif
+}
+
+pub async fn export_qif(
+    Path(account_id): Path<i32>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> impl IntoResponse {
+    let account_model = match account::Entity::find_by_id(account_id).one(&db).await {
+        Ok(Some(account_model)) => account_model,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Errore nel recupero account: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let transactions = match transaction::Entity::find()
+        .filter(transaction::Column::AccountId.eq(account_id))
+        .order_by_asc(transaction::Column::Date)
+        .find_also_related(category::Entity)
+        .all(&db)
+        .await
+    {
+        Ok(transactions) => transactions,
+        Err(e) => {
+            eprintln!("Errore nel recupero delle transazioni: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let filename: String = account_model
+        .name
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/qif")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}.qif\"", filename),
+        )
+        .body(write_qif(transactions).into())
+        .unwrap()
+}
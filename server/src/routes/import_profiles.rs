@@ -0,0 +1,424 @@
+use askama::Template;
+use axum::{
+    extract::{Multipart, Path},
+    http::{header, Response, StatusCode},
+    response::{Html, IntoResponse, Redirect},
+    Extension, Form,
+};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait,
+    PaginatorTrait, QueryFilter, QueryOrder,
+};
+use serde::Deserialize;
+
+use crate::{
+    database::{import_profile, import_profiles::default_profile, settings},
+    routes::{
+        account_transactions::empty_string_as_none,
+        backup::{import_profile_from_dto, import_profile_to_dto, ImportProfileDTO},
+        uploader::{pdf_line_patterns, separator_byte, skip_patterns},
+    },
+};
+
+#[derive(Template)]
+#[template(path = "import_profiles.html")]
+struct ImportProfilesTemplate<'a> {
+    profiles: Vec<ProfileWithUsage>,
+    menu: &'a str,
+}
+
+struct ProfileWithUsage {
+    model: import_profile::Model,
+    accounts: u64,
+}
+
+#[derive(Template)]
+#[template(path = "import_profile.html")]
+struct ImportProfileTemplate<'a> {
+    profile: import_profile::Model,
+    menu: &'a str,
+}
+
+#[derive(Deserialize)]
+pub struct AddImportProfileForm {
+    name: String,
+}
+
+#[derive(Deserialize)]
+pub struct ImportProfileForm {
+    name: String,
+    format: String,
+    starter_string: String,
+    date_index: i32,
+    description_index: i32,
+    value_index: i32,
+    delimiter: String,
+    quote_char: String,
+    encoding: String,
+    decimal_separator: String,
+    thousands_separator: String,
+    date_format: String,
+    date_header: String,
+    description_header: String,
+    value_header: String,
+    currency_header: String,
+    #[serde(deserialize_with = "empty_string_as_none")]
+    currency_index: Option<i32>,
+    counterparty_header: String,
+    #[serde(deserialize_with = "empty_string_as_none")]
+    counterparty_index: Option<i32>,
+    reference_header: String,
+    #[serde(deserialize_with = "empty_string_as_none")]
+    reference_index: Option<i32>,
+    balance_header: String,
+    #[serde(deserialize_with = "empty_string_as_none")]
+    balance_index: Option<i32>,
+    amount_layout: String,
+    debit_header: String,
+    #[serde(deserialize_with = "empty_string_as_none")]
+    debit_index: Option<i32>,
+    credit_header: String,
+    #[serde(deserialize_with = "empty_string_as_none")]
+    credit_index: Option<i32>,
+    direction_header: String,
+    #[serde(deserialize_with = "empty_string_as_none")]
+    direction_index: Option<i32>,
+    debit_markers: String,
+    // Unchecked checkboxes are not sent at all.
+    #[serde(default)]
+    invert_sign: Option<String>,
+    worksheet: String,
+    end_marker: String,
+    #[serde(default)]
+    stop_at_blank_row: Option<String>,
+    skip_patterns: String,
+    pdf_line_patterns: String,
+}
+
+/// A JSON export holds either a single profile or a list of them.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ImportProfilesFile {
+    One(Box<ImportProfileDTO>),
+    Many(Vec<ImportProfileDTO>),
+}
+
+fn valid_profile_form(form: &ImportProfileForm) -> bool {
+    !form.name.trim().is_empty()
+        && [
+            import_profile::FORMAT_AUTO,
+            import_profile::FORMAT_CSV,
+            import_profile::FORMAT_XLSX,
+            import_profile::FORMAT_XLS,
+            import_profile::FORMAT_PDF,
+        ]
+        .contains(&form.format.as_str())
+        && separator_byte(&form.delimiter).is_some()
+        && separator_byte(&form.quote_char).is_some()
+        && encoding_rs::Encoding::for_label(form.encoding.trim().as_bytes()).is_some()
+        && !form.decimal_separator.is_empty()
+        && form.decimal_separator != form.thousands_separator
+        && !form.date_format.is_empty()
+        && [
+            import_profile::AMOUNT_SIGNED,
+            import_profile::AMOUNT_SPLIT,
+            import_profile::AMOUNT_DIRECTION,
+        ]
+        .contains(&form.amount_layout.as_str())
+        && skip_patterns(&form.skip_patterns).is_ok()
+        && pdf_line_patterns(&form.pdf_line_patterns).is_ok()
+}
+
+async fn find_profile(
+    db: &DatabaseConnection,
+    profile_id: i32,
+) -> Result<import_profile::Model, StatusCode> {
+    import_profile::Entity::find_by_id(profile_id)
+        .one(db)
+        .await
+        .map_err(|e| {
+            eprintln!("Errore recupero profilo di import: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+fn json_download(filename: &str, body: String) -> Response<axum::body::Body> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}.json\"", filename),
+        )
+        .body(body.into())
+        .unwrap()
+}
+
+pub async fn get_import_profiles_handler(
+    Extension(db): Extension<DatabaseConnection>,
+) -> Result<Html<String>, StatusCode> {
+    let profiles = import_profile::Entity::find()
+        .order_by_asc(import_profile::Column::Name)
+        .all(&db)
+        .await
+        .map_err(|e| {
+            eprintln!("Errore recupero profili di import: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let mut profiles_with_usage = Vec::new();
+    for model in profiles {
+        let accounts = settings::Entity::find()
+            .filter(settings::Column::ProfileId.eq(model.id))
+            .count(&db)
+            .await
+            .map_err(|e| {
+                eprintln!("Errore conteggio account del profilo: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        profiles_with_usage.push(ProfileWithUsage { model, accounts });
+    }
+
+    let html = ImportProfilesTemplate {
+        profiles: profiles_with_usage,
+        menu: "import_profiles",
+    };
+
+    Ok(Html(html.render().unwrap()))
+}
+
+pub async fn add_import_profile_handler(
+    Extension(db): Extension<DatabaseConnection>,
+    Form(form): Form<AddImportProfileForm>,
+) -> Result<Redirect, StatusCode> {
+    if form.name.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let profile = default_profile(form.name.trim())
+        .insert(&db)
+        .await
+        .map_err(|e| {
+            eprintln!("Errore inserimento profilo di import: {:?}", e);
+            StatusCode::BAD_REQUEST
+        })?;
+
+    Ok(Redirect::to(&format!("/import_profiles/{}", profile.id)))
+}
+
+pub async fn get_import_profile_handler(
+    Path(profile_id): Path<i32>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> Result<Html<String>, StatusCode> {
+    let profile = find_profile(&db, profile_id).await?;
+
+    let html = ImportProfileTemplate {
+        profile,
+        menu: "import_profiles",
+    };
+
+    Ok(Html(html.render().unwrap()))
+}
+
+pub async fn update_import_profile_handler(
+    Path(profile_id): Path<i32>,
+    Extension(db): Extension<DatabaseConnection>,
+    Form(form): Form<ImportProfileForm>,
+) -> Result<Redirect, StatusCode> {
+    if !valid_profile_form(&form) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let profile = find_profile(&db, profile_id).await?;
+    if profile.builtin {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let mut the_profile: import_profile::ActiveModel = profile.into();
+    the_profile.name = Set(form.name.trim().to_string());
+    the_profile.format = Set(form.format);
+    the_profile.starter_string = Set(form.starter_string);
+    the_profile.date_index = Set(form.date_index);
+    the_profile.description_index = Set(form.description_index);
+    the_profile.value_index = Set(form.value_index);
+    the_profile.delimiter = Set(form.delimiter);
+    the_profile.quote_char = Set(form.quote_char);
+    the_profile.encoding = Set(form.encoding);
+    the_profile.decimal_separator = Set(form.decimal_separator);
+    the_profile.thousands_separator = Set(form.thousands_separator);
+    the_profile.date_format = Set(form.date_format);
+    the_profile.date_header = Set(form.date_header);
+    the_profile.description_header = Set(form.description_header);
+    the_profile.value_header = Set(form.value_header);
+    the_profile.currency_header = Set(form.currency_header);
+    the_profile.currency_index = Set(form.currency_index);
+    the_profile.counterparty_header = Set(form.counterparty_header);
+    the_profile.counterparty_index = Set(form.counterparty_index);
+    the_profile.reference_header = Set(form.reference_header);
+    the_profile.reference_index = Set(form.reference_index);
+    the_profile.balance_header = Set(form.balance_header);
+    the_profile.balance_index = Set(form.balance_index);
+    the_profile.amount_layout = Set(form.amount_layout);
+    the_profile.debit_header = Set(form.debit_header);
+    the_profile.debit_index = Set(form.debit_index);
+    the_profile.credit_header = Set(form.credit_header);
+    the_profile.credit_index = Set(form.credit_index);
+    the_profile.direction_header = Set(form.direction_header);
+    the_profile.direction_index = Set(form.direction_index);
+    the_profile.debit_markers = Set(form.debit_markers);
+    the_profile.invert_sign = Set(form.invert_sign.is_some());
+    the_profile.worksheet = Set(form.worksheet.trim().to_string());
+    the_profile.end_marker = Set(form.end_marker);
+    the_profile.stop_at_blank_row = Set(form.stop_at_blank_row.is_some());
+    the_profile.skip_patterns = Set(form.skip_patterns);
+    the_profile.pdf_line_patterns = Set(form.pdf_line_patterns);
+    the_profile.update(&db).await.map_err(|err| {
+        eprintln!("Cannot update import profile: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Redirect::to(&format!("/import_profiles/{}", profile_id)))
+}
+
+pub async fn duplicate_import_profile_handler(
+    Path(profile_id): Path<i32>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> Result<Redirect, StatusCode> {
+    let profile = find_profile(&db, profile_id).await?;
+
+    let mut dto = import_profile_to_dto(profile);
+    dto.name = format!("{} (copia)", dto.name);
+    dto.builtin = false;
+
+    let copy = import_profile_from_dto(dto)
+        .insert(&db)
+        .await
+        .map_err(|e| {
+            eprintln!("Errore duplicando il profilo di import: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Redirect::to(&format!("/import_profiles/{}", copy.id)))
+}
+
+pub async fn delete_import_profile(
+    Path(profile_id): Path<i32>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> impl IntoResponse {
+    match find_profile(&db, profile_id).await {
+        Ok(profile) if profile.builtin => return StatusCode::FORBIDDEN,
+        Ok(_) => {}
+        Err(status) => return status,
+    }
+
+    match import_profile::Entity::delete_by_id(profile_id)
+        .exec(&db)
+        .await
+    {
+        Ok(_) => StatusCode::NO_CONTENT,
+        Err(err) => {
+            eprintln!(
+                "Errore eliminando profilo di import {}: {}",
+                profile_id, err
+            );
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+pub async fn export_import_profile_handler(
+    Path(profile_id): Path<i32>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> impl IntoResponse {
+    let profile = match find_profile(&db, profile_id).await {
+        Ok(profile) => profile,
+        Err(status) => return status.into_response(),
+    };
+
+    let filename: String = profile
+        .name
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+
+    match serde_json::to_string_pretty(&import_profile_to_dto(profile)) {
+        Ok(json) => json_download(&filename, json).into_response(),
+        Err(e) => {
+            eprintln!("Errore serializzando il profilo di import: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn export_import_profiles_handler(
+    Extension(db): Extension<DatabaseConnection>,
+) -> impl IntoResponse {
+    let profiles = match import_profile::Entity::find()
+        .order_by_asc(import_profile::Column::Name)
+        .all(&db)
+        .await
+    {
+        Ok(profiles) => profiles,
+        Err(e) => {
+            eprintln!("Errore recupero profili di import: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let dto: Vec<ImportProfileDTO> = profiles.into_iter().map(import_profile_to_dto).collect();
+
+    match serde_json::to_string_pretty(&dto) {
+        Ok(json) => json_download("import_profiles", json).into_response(),
+        Err(e) => {
+            eprintln!("Errore serializzando i profili di import: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Imported profiles are always added as new, editable profiles, even when
+/// the file comes from a built-in one.
+pub async fn import_import_profiles_handler(
+    Extension(db): Extension<DatabaseConnection>,
+    mut multipart: Multipart,
+) -> impl IntoResponse {
+    let mut profiles = Vec::new();
+
+    while let Ok(Some(field)) = multipart.next_field().await {
+        if field.name() != Some("profiles_file") {
+            continue;
+        }
+
+        let data = match field.bytes().await {
+            Ok(d) => d,
+            Err(err) => {
+                eprintln!("Errore leggendo file: {:?}", err);
+                return (StatusCode::BAD_REQUEST, "Errore leggendo il file").into_response();
+            }
+        };
+
+        match serde_json::from_slice::<ImportProfilesFile>(&data) {
+            Ok(ImportProfilesFile::One(profile)) => profiles.push(*profile),
+            Ok(ImportProfilesFile::Many(many)) => profiles.extend(many),
+            Err(err) => {
+                eprintln!("Errore deserializzando JSON: {:?}", err);
+                return (StatusCode::BAD_REQUEST, "Profilo di import non valido").into_response();
+            }
+        }
+    }
+
+    for mut profile in profiles {
+        profile.builtin = false;
+        if let Err(e) = import_profile_from_dto(profile).insert(&db).await {
+            eprintln!("Errore inserimento profilo di import: {:?}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Errore inserimento profilo di import",
+            )
+                .into_response();
+        }
+    }
+
+    Redirect::to("/import_profiles").into_response()
+}
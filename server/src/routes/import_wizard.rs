@@ -0,0 +1,809 @@
+use std::{collections::HashMap, io::Cursor};
+
+use axum::{
+    extract::{Multipart, Path},
+    http::StatusCode,
+    response::{IntoResponse, Redirect},
+    Extension, Form, Json,
+};
+use calamine::{Data, Range, Reader, Xls, Xlsx};
+use chrono::NaiveDate;
+use csv::ReaderBuilder;
+use encoding_rs::Encoding;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::{
+        entities::{account, import_profile, settings},
+        import_profiles::default_profile,
+    },
+    routes::{
+        account_transactions::empty_string_as_none,
+        uploader::{
+            detect_format, excel_number_to_date, normalize_header, parse_amount, separator_byte,
+            StatementFormat,
+        },
+    },
+};
+
+/// Rows read from the sample, bank exports never put the header further down.
+const SAMPLE_ROWS: usize = 200;
+/// Data rows sent back to show the user what was read.
+const PREVIEW_ROWS: usize = 10;
+
+const DELIMITERS: [&str; 4] = [";", ",", "\t", "|"];
+/// Tried in order, so ambiguous dates like `01/02/2025` are read day first.
+const DATE_FORMATS: [&str; 7] = [
+    "%d/%m/%Y", "%d/%m/%y", "%d-%m-%Y", "%d.%m.%Y", "%Y-%m-%d", "%Y/%m/%d", "%m/%d/%Y",
+];
+
+const DATE_HINTS: [&str; 3] = ["data", "date", "valuta"];
+const DESCRIPTION_HINTS: [&str; 7] = [
+    "descrizione",
+    "causale",
+    "description",
+    "operazione",
+    "dettagli",
+    "memo",
+    "beneficiario",
+];
+const AMOUNT_HINTS: [&str; 4] = ["importo", "amount", "valore", "ammontare"];
+const DEBIT_HINTS: [&str; 4] = ["uscit", "addebit", "dare", "debit"];
+const CREDIT_HINTS: [&str; 4] = ["entrat", "accredit", "avere", "credit"];
+const BALANCE_HINTS: [&str; 2] = ["saldo", "balance"];
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum ColumnKind {
+    Date,
+    Description,
+    Amount,
+    Debit,
+    Credit,
+    Balance,
+    Other,
+}
+
+/// What a column most likely holds. The confidence goes from 0 to 1 and
+/// weighs how many cells look right plus whether the header agrees.
+#[derive(Serialize)]
+struct ColumnGuess {
+    index: usize,
+    header: String,
+    kind: ColumnKind,
+    confidence: f64,
+}
+
+#[derive(Serialize)]
+struct DetectionResult {
+    format: &'static str,
+    delimiter: String,
+    encoding: String,
+    decimal_separator: String,
+    thousands_separator: String,
+    date_format: String,
+    /// Line of the file, or row of the sheet, holding the header.
+    header_row: Option<usize>,
+    header: Vec<String>,
+    rows: Vec<Vec<String>>,
+    columns: Vec<ColumnGuess>,
+    amount_layout: &'static str,
+    date_index: Option<usize>,
+    description_index: Option<usize>,
+    value_index: Option<usize>,
+    debit_index: Option<usize>,
+    credit_index: Option<usize>,
+    balance_index: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct ImportWizardForm {
+    name: String,
+    format: String,
+    delimiter: String,
+    encoding: String,
+    decimal_separator: String,
+    thousands_separator: String,
+    date_format: String,
+    amount_layout: String,
+    date_index: i32,
+    description_index: i32,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    value_index: Option<i32>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    debit_index: Option<i32>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    credit_index: Option<i32>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    balance_index: Option<i32>,
+    #[serde(default)]
+    date_header: String,
+    #[serde(default)]
+    description_header: String,
+    #[serde(default)]
+    value_header: String,
+    #[serde(default)]
+    debit_header: String,
+    #[serde(default)]
+    credit_header: String,
+    #[serde(default)]
+    balance_header: String,
+}
+
+/// The first rows of the sample as text cells, with how they were read.
+struct SampleGrid {
+    rows: Vec<Vec<String>>,
+    /// Line or spreadsheet row of each row, blank lines are not rows.
+    lines: Vec<usize>,
+    delimiter: String,
+    encoding: String,
+}
+
+/// Reads the CSV sample with every candidate delimiter and keeps the one
+/// giving the most rows with the same number of columns.
+fn read_csv_sample(data: &[u8]) -> SampleGrid {
+    let (encoding, text) = match Encoding::for_bom(data) {
+        Some((encoding, _)) => (encoding, encoding.decode(data).0),
+        None => match std::str::from_utf8(data) {
+            Ok(text) => (encoding_rs::UTF_8, text.into()),
+            Err(_) => (
+                encoding_rs::WINDOWS_1252,
+                encoding_rs::WINDOWS_1252.decode(data).0,
+            ),
+        },
+    };
+    let text = text.trim_start_matches('\u{feff}');
+
+    let mut best_score = None;
+    let mut best_delimiter = DELIMITERS[0];
+    let mut best_rows = Vec::new();
+    for delimiter in DELIMITERS {
+        let rows: Vec<(usize, Vec<String>)> = ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .delimiter(delimiter.as_bytes()[0])
+            .from_reader(Cursor::new(text.as_bytes()))
+            .records()
+            .take(SAMPLE_ROWS)
+            .filter_map(|r| r.ok())
+            .enumerate()
+            .map(|(idx, r)| {
+                let line = r.position().map(|p| p.line() as usize).unwrap_or(idx + 1);
+                (line, r.iter().map(|v| v.trim().to_string()).collect())
+            })
+            .collect();
+
+        let mut widths: HashMap<usize, usize> = HashMap::new();
+        for (_, row) in rows.iter().filter(|(_, r)| r.len() > 1) {
+            *widths.entry(row.len()).or_default() += 1;
+        }
+        let score = widths
+            .into_iter()
+            .map(|(width, count)| (count, width))
+            .max()
+            .unwrap_or_default();
+
+        if best_score.is_none_or(|best| score > best) {
+            best_score = Some(score);
+            best_delimiter = delimiter;
+            best_rows = rows;
+        }
+    }
+
+    let (lines, rows) = best_rows.into_iter().unzip();
+    SampleGrid {
+        rows,
+        lines,
+        delimiter: if best_delimiter == "\t" {
+            "\\t".to_string()
+        } else {
+            best_delimiter.to_string()
+        },
+        encoding: encoding.name().to_lowercase(),
+    }
+}
+
+fn read_worksheet_sample(range: Option<Result<Range<Data>, impl std::fmt::Debug>>) -> SampleGrid {
+    let (lines, rows) = match range {
+        Some(Ok(range)) => {
+            let first_row = range.start().map(|(row, _)| row as usize).unwrap_or(0);
+            range
+                .rows()
+                .take(SAMPLE_ROWS)
+                .enumerate()
+                .map(|(idx, row)| {
+                    let cells = row
+                        .iter()
+                        .map(|c| c.to_string().trim().to_string())
+                        .collect();
+                    (first_row + idx + 1, cells)
+                })
+                .unzip()
+        }
+        Some(Err(e)) => {
+            eprintln!("Errore leggendo il foglio di lavoro: {:?}", e);
+            Default::default()
+        }
+        None => Default::default(),
+    };
+
+    SampleGrid {
+        rows,
+        lines,
+        delimiter: ";".to_string(),
+        encoding: "utf-8".to_string(),
+    }
+}
+
+/// Reads cells the way the importer will: spreadsheets store dates as serial
+/// numbers, CSV files as text in one of the common layouts.
+struct CellReader {
+    spreadsheet: bool,
+    decimal_separator: &'static str,
+    thousands_separator: &'static str,
+}
+
+impl CellReader {
+    fn date_format(&self, cell: &str) -> Option<&'static str> {
+        if self.spreadsheet {
+            let serial: f64 = cell.parse().ok()?;
+            return ((20000.0..80000.0).contains(&serial) && serial.fract() == 0.0)
+                .then(|| excel_number_to_date(cell))
+                .flatten()
+                .map(|_| "excel");
+        }
+
+        DATE_FORMATS
+            .into_iter()
+            .find(|format| NaiveDate::parse_from_str(cell, format).is_ok())
+    }
+
+    fn number(&self, cell: &str) -> Option<f64> {
+        if !cell.chars().any(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        if self.spreadsheet {
+            return cell.replace(',', ".").parse().ok();
+        }
+        parse_amount(cell, self.decimal_separator, self.thousands_separator)
+    }
+}
+
+/// Italian banks write `1.234,56`, foreign ones `1,234.56`: whichever shape
+/// shows up more often wins.
+fn guess_separators(rows: &[Vec<String>]) -> (&'static str, &'static str) {
+    let (mut comma, mut dot) = (0, 0);
+    for cell in rows.iter().flatten() {
+        let cell: String = cell
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '€')
+            .collect();
+        let cell = cell.trim_start_matches(['-', '+']);
+        if !cell
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == '.' || c == ',')
+        {
+            continue;
+        }
+        match (cell.rfind(','), cell.rfind('.')) {
+            (Some(c), d) if d.is_none_or(|d| d < c) && cell.len() - c <= 3 => comma += 1,
+            (c, Some(d)) if c.is_none_or(|c| c < d) && cell.len() - d <= 3 => dot += 1,
+            _ => {}
+        }
+    }
+
+    if dot > comma {
+        (".", ",")
+    } else {
+        (",", ".")
+    }
+}
+
+/// A movement row has a date and at least one more number in it.
+fn is_data_row(row: &[String], cells: &CellReader) -> bool {
+    let Some(date_col) = row.iter().position(|c| cells.date_format(c).is_some()) else {
+        return false;
+    };
+    row.iter()
+        .enumerate()
+        .any(|(i, c)| i != date_col && cells.number(c).is_some())
+}
+
+fn hint(header: &str, hints: &[&str]) -> bool {
+    let header = normalize_header(header);
+    !header.is_empty() && hints.iter().any(|h| header.contains(h))
+}
+
+struct ColumnStats {
+    filled: f64,
+    dates: f64,
+    numbers: f64,
+    texts: f64,
+    has_negatives: bool,
+    average_length: f64,
+}
+
+fn column_stats(rows: &[&Vec<String>], index: usize, cells: &CellReader) -> ColumnStats {
+    let values: Vec<&str> = rows
+        .iter()
+        .filter_map(|r| r.get(index))
+        .map(|c| c.as_str())
+        .filter(|c| !c.is_empty())
+        .collect();
+    let filled = values.len() as f64;
+    let ratio = |count: usize| {
+        if filled > 0.0 {
+            count as f64 / filled
+        } else {
+            0.0
+        }
+    };
+
+    let dates = values
+        .iter()
+        .filter(|c| cells.date_format(c).is_some())
+        .count();
+    let numbers: Vec<f64> = values.iter().filter_map(|c| cells.number(c)).collect();
+    let texts = values
+        .iter()
+        .filter(|c| cells.date_format(c).is_none() && cells.number(c).is_none())
+        .count();
+
+    ColumnStats {
+        filled: filled / rows.len().max(1) as f64,
+        dates: ratio(dates),
+        numbers: ratio(numbers.len()),
+        texts: ratio(texts),
+        has_negatives: numbers.iter().any(|n| *n < 0.0),
+        average_length: if filled > 0.0 {
+            values.iter().map(|c| c.chars().count()).sum::<usize>() as f64 / filled
+        } else {
+            0.0
+        },
+    }
+}
+
+fn confidence(content: f64, hinted: bool) -> f64 {
+    let score = 0.8 * content + if hinted { 0.2 } else { 0.0 };
+    (score.clamp(0.0, 1.0) * 100.0).round() / 100.0
+}
+
+fn detect_layout(grid: SampleGrid, format: StatementFormat) -> Option<DetectionResult> {
+    let spreadsheet = format != StatementFormat::Csv;
+    let (decimal_separator, thousands_separator) = if spreadsheet {
+        (".", "")
+    } else {
+        guess_separators(&grid.rows)
+    };
+    let cells = CellReader {
+        spreadsheet,
+        decimal_separator,
+        thousands_separator,
+    };
+
+    let first_data = grid.rows.iter().position(|r| is_data_row(r, &cells))?;
+
+    // The header is the row right above the first movement, if it is made of
+    // labels only.
+    let header_row = grid.rows[..first_data]
+        .iter()
+        .rposition(|r| r.iter().any(|c| !c.is_empty()))
+        .filter(|&i| {
+            let labels: Vec<&String> = grid.rows[i].iter().filter(|c| !c.is_empty()).collect();
+            labels.len() > 1
+                && labels
+                    .iter()
+                    .all(|c| cells.date_format(c).is_none() && cells.number(c).is_none())
+        });
+    let header = header_row.map(|i| grid.rows[i].clone()).unwrap_or_default();
+
+    // Footers with totals are left out by keeping movement rows only.
+    let data_rows: Vec<&Vec<String>> = grid.rows[first_data..]
+        .iter()
+        .filter(|r| is_data_row(r, &cells))
+        .collect();
+    let width = data_rows.iter().map(|r| r.len()).max().unwrap_or(0);
+
+    let mut date_formats: HashMap<&str, usize> = HashMap::new();
+    for cell in data_rows.iter().flat_map(|r| r.iter()) {
+        if let Some(format) = cells.date_format(cell) {
+            *date_formats.entry(format).or_default() += 1;
+        }
+    }
+    // `max_by_key` keeps the last maximum, reversing keeps the first format
+    // in the list on ties.
+    let date_format = DATE_FORMATS
+        .into_iter()
+        .rev()
+        .max_by_key(|format| date_formats.get(format).copied().unwrap_or(0))
+        .unwrap_or(DATE_FORMATS[0]);
+
+    let header_of = |index: usize| header.get(index).cloned().unwrap_or_default();
+    let mut columns: Vec<ColumnGuess> = (0..width)
+        .map(|index| {
+            let stats = column_stats(&data_rows, index, &cells);
+            let name = header_of(index);
+            let (kind, confidence) = if stats.dates >= 0.8 {
+                (
+                    ColumnKind::Date,
+                    confidence(stats.dates * stats.filled, hint(&name, &DATE_HINTS)),
+                )
+            } else if stats.numbers >= 0.8 {
+                let kind = if hint(&name, &BALANCE_HINTS) {
+                    ColumnKind::Balance
+                } else if hint(&name, &DEBIT_HINTS) {
+                    ColumnKind::Debit
+                } else if hint(&name, &CREDIT_HINTS) {
+                    ColumnKind::Credit
+                } else {
+                    ColumnKind::Amount
+                };
+                let hinted = kind != ColumnKind::Amount || hint(&name, &AMOUNT_HINTS);
+                // Split debit/credit columns are half empty by design.
+                let content = match kind {
+                    ColumnKind::Debit | ColumnKind::Credit => stats.numbers,
+                    _ => stats.numbers * stats.filled,
+                };
+                (kind, confidence(content, hinted))
+            } else if stats.texts >= 0.5 {
+                let length = (stats.average_length / 20.0).min(1.0);
+                (
+                    ColumnKind::Description,
+                    confidence(
+                        stats.texts * stats.filled * length,
+                        hint(&name, &DESCRIPTION_HINTS),
+                    ),
+                )
+            } else {
+                (ColumnKind::Other, 0.0)
+            };
+
+            ColumnGuess {
+                index,
+                header: name,
+                kind,
+                confidence,
+            }
+        })
+        .collect();
+
+    let best = |columns: &[ColumnGuess], kind: ColumnKind| {
+        columns
+            .iter()
+            .filter(|c| c.kind == kind)
+            .max_by(|a, b| {
+                a.confidence
+                    .total_cmp(&b.confidence)
+                    .then(b.index.cmp(&a.index))
+            })
+            .map(|c| c.index)
+    };
+
+    // Without headers, two half filled amount columns that never overlap are
+    // a debit/credit pair, debit first as in "Dare | Avere".
+    if best(&columns, ColumnKind::Debit).is_none() && best(&columns, ColumnKind::Credit).is_none() {
+        let partial: Vec<usize> = columns
+            .iter()
+            .filter(|c| c.kind == ColumnKind::Amount)
+            .map(|c| c.index)
+            .filter(|&i| column_stats(&data_rows, i, &cells).filled < 0.95)
+            .collect();
+        if let [debit, credit] = partial[..] {
+            let overlapping = data_rows.iter().any(|r| {
+                [debit, credit]
+                    .iter()
+                    .all(|&i| r.get(i).is_some_and(|c| !c.is_empty()))
+            });
+            if !overlapping {
+                columns[debit].kind = ColumnKind::Debit;
+                columns[credit].kind = ColumnKind::Credit;
+            }
+        }
+    }
+
+    let debit_index = best(&columns, ColumnKind::Debit);
+    let credit_index = best(&columns, ColumnKind::Credit);
+    let split = debit_index.is_some() && credit_index.is_some();
+
+    // A signed amount column has negative values, the balance usually not.
+    let value_index = if split {
+        None
+    } else {
+        columns
+            .iter()
+            .filter(|c| c.kind == ColumnKind::Amount)
+            .max_by(|a, b| {
+                let negatives =
+                    |c: &ColumnGuess| column_stats(&data_rows, c.index, &cells).has_negatives;
+                (hint(&a.header, &AMOUNT_HINTS), negatives(a))
+                    .cmp(&(hint(&b.header, &AMOUNT_HINTS), negatives(b)))
+                    .then(a.confidence.total_cmp(&b.confidence))
+                    .then(b.index.cmp(&a.index))
+            })
+            .map(|c| c.index)
+    };
+
+    Some(DetectionResult {
+        format: match format {
+            StatementFormat::Xlsx => import_profile::FORMAT_XLSX,
+            StatementFormat::Xls => import_profile::FORMAT_XLS,
+            _ => import_profile::FORMAT_CSV,
+        },
+        delimiter: grid.delimiter,
+        encoding: grid.encoding,
+        decimal_separator: decimal_separator.to_string(),
+        thousands_separator: thousands_separator.to_string(),
+        date_format: date_format.to_string(),
+        header_row: header_row.map(|i| grid.lines[i]),
+        header,
+        rows: data_rows
+            .iter()
+            .take(PREVIEW_ROWS)
+            .map(|r| r.to_vec())
+            .collect(),
+        amount_layout: if split {
+            import_profile::AMOUNT_SPLIT
+        } else {
+            import_profile::AMOUNT_SIGNED
+        },
+        date_index: best(&columns, ColumnKind::Date),
+        description_index: best(&columns, ColumnKind::Description),
+        value_index,
+        debit_index: if split { debit_index } else { None },
+        credit_index: if split { credit_index } else { None },
+        balance_index: best(&columns, ColumnKind::Balance),
+        columns,
+    })
+}
+
+/// Reads a sample statement and guesses how to map it: where the header is,
+/// which columns hold dates, amounts and descriptions and how numbers and
+/// dates are written. Nothing is saved, the user confirms the guesses first.
+pub async fn detect_import_layout_handler(
+    Path(account_id): Path<i32>,
+    Extension(db): Extension<DatabaseConnection>,
+    mut multipart: Multipart,
+) -> impl IntoResponse {
+    match account::Entity::find_by_id(account_id).one(&db).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Errore nel recupero account: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+
+    let data = match multipart.next_field().await {
+        Ok(Some(field)) => match field.bytes().await {
+            Ok(data) => data,
+            Err(e) => {
+                eprintln!("Errore nella lettura del file di esempio: {:?}", e);
+                return (StatusCode::BAD_REQUEST, "Upload non valido").into_response();
+            }
+        },
+        Ok(None) => return (StatusCode::BAD_REQUEST, "Nessun file caricato").into_response(),
+        Err(e) => {
+            eprintln!("Errore nella lettura dell'upload: {:?}", e);
+            return (StatusCode::BAD_REQUEST, "Upload non valido").into_response();
+        }
+    };
+
+    let format = detect_format(&data);
+    let grid = match format {
+        Some(StatementFormat::Csv) => read_csv_sample(&data),
+        Some(StatementFormat::Xlsx) => match Xlsx::new(Cursor::new(data.as_ref())) {
+            Ok(mut workbook) => read_worksheet_sample(workbook.worksheet_range_at(0)),
+            Err(e) => {
+                eprintln!("Errore aprendo il file xlsx: {:?}", e);
+                return (StatusCode::BAD_REQUEST, "File Excel non valido").into_response();
+            }
+        },
+        Some(StatementFormat::Xls) => match Xls::new(Cursor::new(data.as_ref())) {
+            Ok(mut workbook) => read_worksheet_sample(workbook.worksheet_range_at(0)),
+            Err(e) => {
+                eprintln!("Errore aprendo il file xls: {:?}", e);
+                return (StatusCode::BAD_REQUEST, "File Excel non valido").into_response();
+            }
+        },
+        _ => {
+            return (
+                StatusCode::BAD_REQUEST,
+                "Il riconoscimento delle colonne funziona solo con file CSV ed Excel",
+            )
+                .into_response();
+        }
+    };
+
+    match format.and_then(|format| detect_layout(grid, format)) {
+        Some(result) => (StatusCode::OK, Json(result)).into_response(),
+        None => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Nessuna riga di movimenti riconosciuta nel file",
+        )
+            .into_response(),
+    }
+}
+
+fn valid_wizard_form(form: &ImportWizardForm) -> bool {
+    let amount_columns = match form.amount_layout.as_str() {
+        import_profile::AMOUNT_SIGNED => form.value_index.is_some(),
+        import_profile::AMOUNT_SPLIT => form.debit_index.is_some() && form.credit_index.is_some(),
+        _ => false,
+    };
+
+    !form.name.trim().is_empty()
+        && [
+            import_profile::FORMAT_CSV,
+            import_profile::FORMAT_XLSX,
+            import_profile::FORMAT_XLS,
+        ]
+        .contains(&form.format.as_str())
+        && separator_byte(&form.delimiter).is_some()
+        && Encoding::for_label(form.encoding.as_bytes()).is_some()
+        && !form.date_format.trim().is_empty()
+        && amount_columns
+}
+
+/// Saves the confirmed guesses as a new import profile and selects it for the
+/// account. Header names are stored too, so the profile keeps working if the
+/// bank reorders the columns.
+pub async fn save_import_wizard_handler(
+    Path(account_id): Path<i32>,
+    Extension(db): Extension<DatabaseConnection>,
+    Form(form): Form<ImportWizardForm>,
+) -> Result<Redirect, StatusCode> {
+    if !valid_wizard_form(&form) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let account_settings = settings::Entity::find()
+        .filter(settings::Column::AccountId.eq(account_id))
+        .one(&db)
+        .await
+        .map_err(|e| {
+            eprintln!("Errore query settings: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    // Header names are `|` separated aliases in a profile.
+    let alias = |header: String| header.replace('|', " ").trim().to_string();
+
+    let mut profile = default_profile(form.name.trim());
+    profile.format = Set(form.format);
+    profile.delimiter = Set(form.delimiter);
+    profile.encoding = Set(form.encoding);
+    profile.decimal_separator = Set(form.decimal_separator);
+    profile.thousands_separator = Set(form.thousands_separator);
+    profile.date_format = Set(form.date_format.trim().to_string());
+    profile.amount_layout = Set(form.amount_layout);
+    profile.date_index = Set(form.date_index);
+    profile.date_header = Set(alias(form.date_header));
+    profile.description_index = Set(form.description_index);
+    profile.description_header = Set(alias(form.description_header));
+    profile.value_index = Set(form.value_index.unwrap_or(0));
+    profile.value_header = Set(alias(form.value_header));
+    profile.debit_index = Set(form.debit_index);
+    profile.debit_header = Set(alias(form.debit_header));
+    profile.credit_index = Set(form.credit_index);
+    profile.credit_header = Set(alias(form.credit_header));
+    profile.balance_index = Set(form.balance_index);
+    profile.balance_header = Set(alias(form.balance_header));
+
+    let profile = profile.insert(&db).await.map_err(|e| {
+        eprintln!("Errore creazione profilo di import: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let result = match account_settings {
+        Some(account_settings) => {
+            let mut the_settings: settings::ActiveModel = account_settings.into();
+            the_settings.profile_id = Set(Some(profile.id));
+            the_settings.update(&db).await
+        }
+        None => {
+            settings::ActiveModel {
+                account_id: Set(account_id),
+                profile_id: Set(Some(profile.id)),
+                auto_apply_rules: Set(false),
+                ..Default::default()
+            }
+            .insert(&db)
+            .await
+        }
+    };
+
+    result.map_err(|e| {
+        eprintln!("Errore aggiornando settings: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Redirect::to(&format!("/accounts/{}/settings", account_id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(cells: &[&[&str]]) -> Vec<Vec<String>> {
+        cells
+            .iter()
+            .map(|r| r.iter().map(|c| c.to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn separators_follow_the_most_common_shape() {
+        let italian = rows(&[&["1.234,56", "-12,50", "abc"], &["3,00", "2025"]]);
+        assert_eq!(guess_separators(&italian), (",", "."));
+
+        let english = rows(&[&["1,234.56", "-12.50 €"], &["3.00", "1,234"]]);
+        assert_eq!(guess_separators(&english), (".", ","));
+
+        assert_eq!(guess_separators(&[]), (",", "."));
+    }
+
+    #[test]
+    fn csv_layout_with_preamble_and_split_amounts() {
+        let sample = "\
+Estratto conto;;;;
+Conto;IT00X0000000000000000000000;;;
+;;;;
+Data;Descrizione operazione;Dare;Avere;Saldo
+02/01/2025;Pagamento POS supermercato;45,30;;1.954,70
+03/01/2025;Bonifico stipendio ACME SRL;;1.500,00;3.454,70
+05/01/2025;Addebito utenze luce e gas;120,00;;3.334,70
+Totale;;165,30;1.500,00;
+";
+        let grid = read_csv_sample(sample.as_bytes());
+        assert_eq!(grid.delimiter, ";");
+        assert_eq!(grid.encoding, "utf-8");
+
+        let result = detect_layout(grid, StatementFormat::Csv).unwrap();
+        assert_eq!(result.format, import_profile::FORMAT_CSV);
+        assert_eq!(result.decimal_separator, ",");
+        assert_eq!(result.thousands_separator, ".");
+        assert_eq!(result.date_format, "%d/%m/%Y");
+        assert_eq!(result.header_row, Some(4));
+        assert_eq!(result.header[1], "Descrizione operazione");
+        // The totals footer is not a movement.
+        assert_eq!(result.rows.len(), 3);
+        assert_eq!(result.amount_layout, import_profile::AMOUNT_SPLIT);
+        assert_eq!(result.date_index, Some(0));
+        assert_eq!(result.description_index, Some(1));
+        assert_eq!(result.value_index, None);
+        assert_eq!(result.debit_index, Some(2));
+        assert_eq!(result.credit_index, Some(3));
+        assert_eq!(result.balance_index, Some(4));
+    }
+
+    #[test]
+    fn headerless_layout_with_signed_amount() {
+        let sample = "\
+2025-01-02,Card payment grocery store,-45.30,1954.70
+2025-01-03,Salary transfer ACME LTD,1500.00,3454.70
+2025-01-05,Electricity and gas bill,-120.00,3334.70
+";
+        let result =
+            detect_layout(read_csv_sample(sample.as_bytes()), StatementFormat::Csv).unwrap();
+        assert_eq!(result.header_row, None);
+        assert!(result.header.is_empty());
+        assert_eq!(result.decimal_separator, ".");
+        assert_eq!(result.date_format, "%Y-%m-%d");
+        assert_eq!(result.amount_layout, import_profile::AMOUNT_SIGNED);
+        assert_eq!(result.date_index, Some(0));
+        assert_eq!(result.description_index, Some(1));
+        // Negative values tell the amount apart from the balance.
+        assert_eq!(result.value_index, Some(2));
+        assert_eq!(result.debit_index, None);
+        assert_eq!(result.credit_index, None);
+    }
+
+    #[test]
+    fn no_movements_no_layout() {
+        let grid = read_csv_sample(b"Nome;Cognome\nMario;Rossi\n");
+        assert!(detect_layout(grid, StatementFormat::Csv).is_none());
+    }
+}
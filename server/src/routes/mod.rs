@@ -1,16 +1,23 @@
 pub mod account_budgets;
 pub mod account_detail;
+pub mod account_imports;
 pub mod account_rules;
 pub mod account_settings;
 pub mod account_transactions;
 pub mod accounts;
+pub mod api;
 pub mod backup;
 pub mod budgets;
 pub mod categories;
 pub mod common;
+pub mod export;
+pub mod import_profiles;
+pub mod import_wizard;
 pub mod report;
 pub mod routes;
+pub mod rule_conditions;
 pub mod rules;
+pub mod running_balance;
 pub mod transactions;
 pub mod uploader;
 pub mod utilities;
@@ -8,6 +8,11 @@ use tower_http::services::ServeDir;
 use crate::routes::{
     account_budgets::{add_budget_handler, get_account_budgets_handler},
     account_detail::{get_account_detail, get_chart_data, get_expenses_report},
+    account_imports::{
+        commit_import_batch_handler, discard_import_batch_handler, download_import_file_handler,
+        drop_import_row_handler, edit_import_row_handler, get_account_imports_handler,
+        get_import_batch_handler, restore_import_row_handler, rollback_import_batch_handler,
+    },
     account_rules::{
         activate_rule_handler, add_account_rule_handler, apply_rules, deactivate_rule_handler,
         get_account_rules_handler, preview_apply_rules, resolve_conflicts_rules,
@@ -15,11 +20,21 @@ use crate::routes::{
     account_settings::{get_account_setting_handler, update_setting_handler},
     account_transactions::{add_transaction_handler, get_account_transactions_handler},
     accounts::{create_account, delete_account, get_all_accounts_handler},
+    api::add_transactions_batch_handler,
     budgets::{delete_budget, edit_budget, get_budgets_handler},
     categories::{add_category_handler, delete_category, edit_category, get_categories_handler},
-    rules::{delete_rule, edit_rule, get_rules_handler},
+    export::export_qif,
+    import_profiles::{
+        add_import_profile_handler, delete_import_profile, duplicate_import_profile_handler,
+        export_import_profile_handler, export_import_profiles_handler, get_import_profile_handler,
+        get_import_profiles_handler, import_import_profiles_handler, update_import_profile_handler,
+    },
+    import_wizard::{detect_import_layout_handler, save_import_wizard_handler},
+    rules::{
+        delete_rule, edit_rule, get_rules_handler, test_draft_rule_handler, test_rule_handler,
+    },
     transactions::{delete_transaction, edit_transaction},
-    uploader::upload_transaction_file,
+    uploader::{reprocess_import_batch_handler, upload_transaction_file},
     utilities::{get_backup_handler, get_utilities_handler, restore_full_backup},
 };
 
@@ -60,10 +75,56 @@ pub fn account_routers() -> Router {
             post(resolve_conflicts_rules),
         )
         .route("/{account_id}/upload", post(upload_transaction_file))
+        .route("/{account_id}/imports", get(get_account_imports_handler))
+        .route(
+            "/{account_id}/imports/{batch_id}",
+            get(get_import_batch_handler),
+        )
+        .route(
+            "/{account_id}/imports/{batch_id}",
+            delete(discard_import_batch_handler),
+        )
+        .route(
+            "/{account_id}/imports/{batch_id}/commit",
+            post(commit_import_batch_handler),
+        )
+        .route(
+            "/{account_id}/imports/{batch_id}/rollback",
+            post(rollback_import_batch_handler),
+        )
+        .route(
+            "/{account_id}/imports/{batch_id}/reprocess",
+            post(reprocess_import_batch_handler),
+        )
+        .route(
+            "/{account_id}/imports/{batch_id}/files/{file_id}",
+            get(download_import_file_handler),
+        )
+        .route(
+            "/{account_id}/imports/{batch_id}/rows/{row_id}",
+            post(edit_import_row_handler),
+        )
+        .route(
+            "/{account_id}/imports/{batch_id}/rows/{row_id}/drop",
+            post(drop_import_row_handler),
+        )
+        .route(
+            "/{account_id}/imports/{batch_id}/rows/{row_id}/restore",
+            post(restore_import_row_handler),
+        )
         .route("/{account_id}/settings", get(get_account_setting_handler))
         .route("/{account_id}/settings", post(update_setting_handler))
+        .route(
+            "/{account_id}/import_wizard",
+            post(save_import_wizard_handler),
+        )
+        .route(
+            "/{account_id}/import_wizard/detect",
+            post(detect_import_layout_handler),
+        )
         .route("/{account_id}/charts", get(get_chart_data))
         .route("/{account_id}/report", get(get_expenses_report))
+        .route("/{account_id}/export/qif", get(export_qif))
 }
 
 pub fn category_routers() -> Router {
@@ -77,8 +138,10 @@ pub fn category_routers() -> Router {
 pub fn rule_routers() -> Router {
     Router::new()
         .route("/", get(get_rules_handler))
+        .route("/test", post(test_draft_rule_handler))
         .route("/{rule_id}", delete(delete_rule))
         .route("/{rule_id}", post(edit_rule))
+        .route("/{rule_id}/test", get(test_rule_handler))
 }
 
 pub fn budget_routers() -> Router {
@@ -94,6 +157,22 @@ pub fn transaction_routers() -> Router {
         .route("/{transaction_id}", post(edit_transaction))
 }
 
+pub fn import_profile_routers() -> Router {
+    Router::new()
+        .route("/", get(get_import_profiles_handler))
+        .route("/", post(add_import_profile_handler))
+        .route("/export", get(export_import_profiles_handler))
+        .route("/import", post(import_import_profiles_handler))
+        .route("/{profile_id}", get(get_import_profile_handler))
+        .route("/{profile_id}", post(update_import_profile_handler))
+        .route("/{profile_id}", delete(delete_import_profile))
+        .route(
+            "/{profile_id}/duplicate",
+            post(duplicate_import_profile_handler),
+        )
+        .route("/{profile_id}/export", get(export_import_profile_handler))
+}
+
 pub fn utilities_routers() -> Router {
     Router::new()
         .route("/", get(get_utilities_handler))
@@ -101,6 +180,13 @@ pub fn utilities_routers() -> Router {
         .route("/restore", post(restore_full_backup))
 }
 
+pub fn api_routers() -> Router {
+    Router::new().route(
+        "/accounts/{account_id}/transactions:batch",
+        post(add_transactions_batch_handler),
+    )
+}
+
 pub fn router() -> Router {
     Router::new()
         .nest_service("/static", ServeDir::new("static"))
@@ -110,5 +196,7 @@ pub fn router() -> Router {
         .nest("/rules", rule_routers())
         .nest("/budgets", budget_routers())
         .nest("/transactions", transaction_routers())
+        .nest("/import_profiles", import_profile_routers())
         .nest("/utilities", utilities_routers())
+        .nest("/api", api_routers())
 }
@@ -0,0 +1,373 @@
+use chrono::{Datelike, NaiveDate, Weekday};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// What a rule condition can look at in a transaction.
+pub struct Facts<'a> {
+    pub description: &'a str,
+    pub value: f64,
+    pub date: NaiveDate,
+    /// Label the transaction has now, empty when it has none.
+    pub label: &'a str,
+}
+
+/// A rule condition as stored in `rules.conditions`, for example
+/// `{"and": [{"description": "AMAZON"}, {"not": {"amount": {"min": 100}}}]}`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    /// Every condition matches, an empty list always matches.
+    And(Vec<Condition>),
+    /// At least one condition matches, an empty list never matches.
+    Or(Vec<Condition>),
+    Not(Box<Condition>),
+    /// Regular expression searched in the description.
+    Description(String),
+    Amount(AmountCondition),
+    DateRange(DateRange),
+    /// Written as `"mon"`, `"Monday"`, ...
+    Weekday(Vec<Weekday>),
+    DayOfMonth(Vec<u32>),
+    /// Current label of the transaction, ignoring case. `""` matches
+    /// transactions without a label.
+    Label(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Sign {
+    Income,
+    Expense,
+}
+
+/// Bounds are inclusive and compared with the absolute value, so "over 100"
+/// reads the same for an expense of -150 and an income of 150. The sign is
+/// checked on its own.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AmountCondition {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exact: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tolerance: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sign: Option<Sign>,
+}
+
+/// Inclusive date range, either end can be left open.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DateRange {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<NaiveDate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<NaiveDate>,
+}
+
+/// A condition checked and ready to be evaluated, with its regular
+/// expressions built once.
+pub enum CompiledCondition {
+    And(Vec<CompiledCondition>),
+    Or(Vec<CompiledCondition>),
+    Not(Box<CompiledCondition>),
+    Description(Regex),
+    Amount(AmountCondition),
+    DateRange(DateRange),
+    Weekday(Vec<Weekday>),
+    DayOfMonth(Vec<u32>),
+    Label(String),
+}
+
+impl AmountCondition {
+    pub fn is_empty(&self) -> bool {
+        self.min.is_none() && self.max.is_none() && self.exact.is_none() && self.sign.is_none()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for amount in [self.min, self.max, self.exact, self.tolerance]
+            .into_iter()
+            .flatten()
+        {
+            if !amount.is_finite() || amount < 0.0 {
+                return Err(format!("Importo non valido: {}", amount));
+            }
+        }
+        if let (Some(min), Some(max)) = (self.min, self.max) {
+            if min > max {
+                return Err(format!(
+                    "Importo minimo maggiore del massimo: {} > {}",
+                    min, max
+                ));
+            }
+        }
+        if self.tolerance.is_some() && self.exact.is_none() {
+            return Err("Tolleranza senza importo esatto".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn matches(&self, value: f64) -> bool {
+        let amount = value.abs();
+        let sign_matches = match self.sign {
+            Some(Sign::Income) => value > 0.0,
+            Some(Sign::Expense) => value < 0.0,
+            None => true,
+        };
+        // Half a cent absorbs the float representation of the amounts.
+        let tolerance = self.tolerance.unwrap_or(0.0) + 0.005;
+
+        sign_matches
+            && self.min.is_none_or(|min| amount >= min)
+            && self.max.is_none_or(|max| amount <= max)
+            && self
+                .exact
+                .is_none_or(|exact| (amount - exact).abs() < tolerance)
+    }
+}
+
+impl DateRange {
+    pub fn matches(&self, date: NaiveDate) -> bool {
+        self.from.is_none_or(|from| date >= from) && self.to.is_none_or(|to| date <= to)
+    }
+}
+
+impl Condition {
+    /// How narrow the condition is, counted in elementary conditions that
+    /// must all hold. An `or` is as specific as its loosest branch.
+    pub fn specificity(&self) -> usize {
+        match self {
+            Condition::And(conditions) => conditions.iter().map(Condition::specificity).sum(),
+            Condition::Or(conditions) => conditions
+                .iter()
+                .map(Condition::specificity)
+                .min()
+                .unwrap_or(0),
+            Condition::Not(condition) => condition.specificity(),
+            _ => 1,
+        }
+    }
+
+    /// Checks the condition and builds its regular expressions. Errors are
+    /// meant for the user, in the language of the other form messages.
+    pub fn compile(&self) -> Result<CompiledCondition, String> {
+        Ok(match self {
+            Condition::And(conditions) => CompiledCondition::And(
+                conditions
+                    .iter()
+                    .map(Condition::compile)
+                    .collect::<Result<_, _>>()?,
+            ),
+            Condition::Or(conditions) => CompiledCondition::Or(
+                conditions
+                    .iter()
+                    .map(Condition::compile)
+                    .collect::<Result<_, _>>()?,
+            ),
+            Condition::Not(condition) => CompiledCondition::Not(Box::new(condition.compile()?)),
+            Condition::Description(pattern) => CompiledCondition::Description(
+                Regex::new(pattern)
+                    .map_err(|e| format!("Espressione regolare non valida '{}': {}", pattern, e))?,
+            ),
+            Condition::Amount(amount) => {
+                amount.validate()?;
+                CompiledCondition::Amount(amount.clone())
+            }
+            Condition::DateRange(range) => {
+                if let (Some(from), Some(to)) = (range.from, range.to) {
+                    if from > to {
+                        return Err(format!("Intervallo di date vuoto: {} > {}", from, to));
+                    }
+                }
+                CompiledCondition::DateRange(range.clone())
+            }
+            Condition::Weekday(days) => CompiledCondition::Weekday(days.clone()),
+            Condition::DayOfMonth(days) => {
+                if let Some(day) = days.iter().find(|d| !(1..=31).contains(*d)) {
+                    return Err(format!("Giorno del mese non valido: {}", day));
+                }
+                CompiledCondition::DayOfMonth(days.clone())
+            }
+            Condition::Label(label) => CompiledCondition::Label(label.trim().to_lowercase()),
+        })
+    }
+}
+
+impl CompiledCondition {
+    pub fn matches(&self, facts: &Facts) -> bool {
+        match self {
+            CompiledCondition::And(conditions) => conditions.iter().all(|c| c.matches(facts)),
+            CompiledCondition::Or(conditions) => conditions.iter().any(|c| c.matches(facts)),
+            CompiledCondition::Not(condition) => !condition.matches(facts),
+            CompiledCondition::Description(regex) => regex.is_match(facts.description),
+            CompiledCondition::Amount(amount) => amount.matches(facts.value),
+            CompiledCondition::DateRange(range) => range.matches(facts.date),
+            CompiledCondition::Weekday(days) => days.contains(&facts.date.weekday()),
+            CompiledCondition::DayOfMonth(days) => days.contains(&facts.date.day()),
+            CompiledCondition::Label(label) => facts.label.trim().to_lowercase() == *label,
+        }
+    }
+}
+
+/// Reads a condition tree sent by the rule forms and checks it can be
+/// evaluated.
+pub fn parse_conditions(json: &str) -> Result<Condition, String> {
+    let condition: Condition =
+        serde_json::from_str(json).map_err(|e| format!("Condizioni non valide: {}", e))?;
+    condition.compile()?;
+    Ok(condition)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn facts<'a>(description: &'a str, value: f64, day: &str, label: &'a str) -> Facts<'a> {
+        Facts {
+            description,
+            value,
+            date: date(day),
+            label,
+        }
+    }
+
+    fn matches(json: &str, facts: &Facts) -> bool {
+        parse_conditions(json)
+            .unwrap()
+            .compile()
+            .unwrap()
+            .matches(facts)
+    }
+
+    #[test]
+    fn and_or_not() {
+        let json = r#"{"and": [
+            {"description": "AMAZON"},
+            {"not": {"amount": {"max": 100}}},
+            {"or": [{"label": ""}, {"label": "shopping"}]}
+        ]}"#;
+
+        assert!(matches(json, &facts("AMAZON EU", -150.0, "2026-10-05", "")));
+        assert!(matches(
+            json,
+            &facts("AMAZON EU", -150.0, "2026-10-05", " Shopping ")
+        ));
+        assert!(!matches(json, &facts("AMAZON EU", -20.0, "2026-10-05", "")));
+        assert!(!matches(json, &facts("EBAY", -150.0, "2026-10-05", "")));
+        assert!(!matches(
+            json,
+            &facts("AMAZON EU", -150.0, "2026-10-05", "casa")
+        ));
+    }
+
+    #[test]
+    fn empty_lists() {
+        let f = facts("x", 1.0, "2026-10-05", "");
+        assert!(matches(r#"{"and": []}"#, &f));
+        assert!(!matches(r#"{"or": []}"#, &f));
+    }
+
+    #[test]
+    fn amount_conditions() {
+        let rent = AmountCondition {
+            exact: Some(750.0),
+            tolerance: Some(5.0),
+            sign: Some(Sign::Expense),
+            ..Default::default()
+        };
+        assert!(rent.matches(-753.0));
+        assert!(rent.matches(-745.0));
+        assert!(!rent.matches(-756.0));
+        assert!(!rent.matches(753.0));
+
+        let exact = AmountCondition {
+            exact: Some(9.99),
+            ..Default::default()
+        };
+        assert!(exact.matches(-9.99));
+        assert!(!exact.matches(-10.0));
+
+        let range = AmountCondition {
+            min: Some(10.0),
+            max: Some(20.0),
+            ..Default::default()
+        };
+        assert!(range.matches(10.0));
+        assert!(range.matches(-20.0));
+        assert!(!range.matches(20.5));
+    }
+
+    #[test]
+    fn open_ended_date_range() {
+        let since = r#"{"date_range": {"from": "2026-10-01"}}"#;
+        assert!(matches(since, &facts("x", 1.0, "2026-10-01", "")));
+        assert!(matches(since, &facts("x", 1.0, "2030-01-01", "")));
+        assert!(!matches(since, &facts("x", 1.0, "2026-09-30", "")));
+
+        let until = r#"{"date_range": {"to": "2026-10-01"}}"#;
+        assert!(matches(until, &facts("x", 1.0, "2026-10-01", "")));
+        assert!(!matches(until, &facts("x", 1.0, "2026-10-02", "")));
+    }
+
+    #[test]
+    fn weekday_and_day_of_month() {
+        // 2026-10-03 is a Saturday.
+        let weekend = r#"{"weekday": ["sat", "Sunday"]}"#;
+        assert!(matches(weekend, &facts("x", 1.0, "2026-10-03", "")));
+        assert!(matches(weekend, &facts("x", 1.0, "2026-10-04", "")));
+        assert!(!matches(weekend, &facts("x", 1.0, "2026-10-05", "")));
+
+        let payday = r#"{"day_of_month": [1, 15]}"#;
+        assert!(matches(payday, &facts("x", 1.0, "2026-10-15", "")));
+        assert!(!matches(payday, &facts("x", 1.0, "2026-10-16", "")));
+    }
+
+    #[test]
+    fn invalid_conditions() {
+        assert!(parse_conditions(r#"{"description": "(unclosed"}"#).is_err());
+        assert!(parse_conditions(r#"{"day_of_month": [0]}"#).is_err());
+        assert!(parse_conditions(r#"{"amount": {"min": 50, "max": 10}}"#).is_err());
+        assert!(parse_conditions(r#"{"amount": {"tolerance": 1}}"#).is_err());
+        assert!(
+            parse_conditions(r#"{"date_range": {"from": "2026-10-02", "to": "2026-10-01"}}"#)
+                .is_err()
+        );
+        assert!(parse_conditions(r#"{"weekday": ["someday"]}"#).is_err());
+        assert!(parse_conditions(r#"{"colour": "red"}"#).is_err());
+    }
+
+    #[test]
+    fn specificity() {
+        let specificity = |json| parse_conditions(json).unwrap().specificity();
+
+        assert_eq!(specificity(r#"{"description": "AMAZON"}"#), 1);
+        assert_eq!(
+            specificity(r#"{"and": [{"description": "AMAZON"}, {"amount": {"min": 100}}]}"#),
+            2
+        );
+        assert_eq!(
+            specificity(
+                r#"{"or": [{"description": "A"}, {"and": [{"label": ""}, {"day_of_month": [1]}]}]}"#
+            ),
+            1
+        );
+        assert_eq!(specificity(r#"{"or": []}"#), 0);
+    }
+
+    #[test]
+    fn round_trip() {
+        let condition = parse_conditions(
+            r#"{"or": [{"description": "A,B{2,3}"}, {"amount": {"sign": "income"}}]}"#,
+        )
+        .unwrap();
+        let json = serde_json::to_string(&condition).unwrap();
+        assert_eq!(parse_conditions(&json).unwrap(), condition);
+    }
+}
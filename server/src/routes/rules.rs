@@ -1,16 +1,36 @@
+use std::collections::HashMap;
+
 use askama::Template;
-use axum::{extract::Path, http::StatusCode, response::IntoResponse, Extension, Form};
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Form, Json,
+};
 use chrono::NaiveDate;
-use sea_orm::{ActiveModelTrait, ActiveValue::Set, DatabaseConnection, EntityTrait};
-use serde::Deserialize;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder,
+};
+use serde::{Deserialize, Serialize};
 
-use crate::database::{category, entities::rule};
+use crate::{
+    database::{account, category, entities::rule, transaction},
+    routes::account_rules::{
+        choose_rule, describe_amount_conditions, get_active_rules, get_conflict_policy,
+        parse_amount_conditions, parse_conditions_field, parse_patterns_field, validate_rule,
+        RuleChoice, RuleMatcher,
+    },
+    routes::account_transactions::empty_string_as_none,
+    routes::rule_conditions::Condition,
+};
 
 #[derive(Template)]
 #[template(path = "rules.html")]
 struct RulesTemplate<'a> {
     rules: Vec<RuleWithCategory>,
     categories: Vec<category::Model>,
+    accounts: Vec<account::Model>,
     menu: &'a str,
 }
 
@@ -22,9 +42,19 @@ struct RuleWithCategory {
     percentage: f32,
     category_id: Option<i32>,
     category_name: String,
-    regexpr: String,
+    /// One pattern per line.
+    patterns: String,
+    patterns_case_insensitive: bool,
     date_start: String,
     date_end: String,
+    amount: String,
+    amount_min: String,
+    amount_max: String,
+    amount_exact: String,
+    amount_tolerance: String,
+    amount_sign: String,
+    conditions: String,
+    priority: i32,
 }
 
 #[derive(Deserialize)]
@@ -33,9 +63,97 @@ pub struct RuleForm {
     label: String,
     percentage: f32,
     category_id: i32,
-    regexpr: String,
+    #[serde(default)]
+    patterns: String,
+    patterns_case_insensitive: Option<String>,
     date_start: String,
     date_end: String,
+    #[serde(default)]
+    amount_min: String,
+    #[serde(default)]
+    amount_max: String,
+    #[serde(default)]
+    amount_exact: String,
+    #[serde(default)]
+    amount_tolerance: String,
+    #[serde(default)]
+    amount_sign: String,
+    #[serde(default)]
+    conditions: String,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    priority: Option<i32>,
+}
+
+/// A rule being edited, tested before it is saved. `id` is the saved rule
+/// it replaces, if any.
+#[derive(Deserialize)]
+pub struct DraftRule {
+    #[serde(default)]
+    id: Option<i32>,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    label: String,
+    #[serde(default)]
+    percentage: f32,
+    category_id: i32,
+    #[serde(default)]
+    patterns: Vec<String>,
+    #[serde(default)]
+    patterns_case_insensitive: bool,
+    #[serde(default)]
+    date_start: Option<NaiveDate>,
+    #[serde(default)]
+    date_end: Option<NaiveDate>,
+    #[serde(default)]
+    amount_min: Option<f64>,
+    #[serde(default)]
+    amount_max: Option<f64>,
+    #[serde(default)]
+    amount_exact: Option<f64>,
+    #[serde(default)]
+    amount_tolerance: Option<f64>,
+    #[serde(default)]
+    amount_sign: Option<String>,
+    #[serde(default)]
+    conditions: Option<Condition>,
+    #[serde(default)]
+    priority: i32,
+}
+
+#[derive(Deserialize)]
+pub struct RuleTestQuery {
+    account_id: i32,
+    /// `all` (default) or `uncategorized`.
+    #[serde(default)]
+    scope: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RuleTestMatch {
+    id: i32,
+    date: String,
+    description: String,
+    value: f64,
+    category: String,
+    /// The rule would set a category other than the current one.
+    category_changes: bool,
+    /// Other active rules of the account matching the transaction too.
+    other_rules: Vec<String>,
+    /// Rule the account conflict policy would apply, none on a conflict.
+    winning_rule: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RuleTestReport {
+    matched: usize,
+    category_changes: usize,
+    conflicts: usize,
+    transactions: Vec<RuleTestMatch>,
+}
+
+fn amount_field(amount: Option<f64>) -> String {
+    amount.map(|a| a.to_string()).unwrap_or_default()
 }
 
 pub async fn get_rules_handler(
@@ -58,13 +176,22 @@ pub async fn get_rules_handler(
             let category_name = cat.map(|c| c.category).unwrap_or_else(|| "-".to_string());
 
             RuleWithCategory {
+                amount: describe_amount_conditions(&model),
+                amount_min: amount_field(model.amount_min),
+                amount_max: amount_field(model.amount_max),
+                amount_exact: amount_field(model.amount_exact),
+                amount_tolerance: amount_field(model.amount_tolerance),
+                amount_sign: model.amount_sign.clone().unwrap_or_default(),
+                conditions: model.conditions.clone().unwrap_or_default(),
+                priority: model.priority,
                 id: model.id,
                 name: model.name,
                 label: model.label,
                 percentage: model.percentage,
                 category_id: Some(category_id),
                 category_name: category_name,
-                regexpr: model.regexpr.unwrap_or("".to_string()),
+                patterns: model.patterns.0.join("\n"),
+                patterns_case_insensitive: model.patterns_case_insensitive,
                 date_start: model.date_start.map(|d| d.to_string()).unwrap_or_default(),
                 date_end: model.date_end.map(|d| d.to_string()).unwrap_or_default(),
             }
@@ -76,9 +203,15 @@ pub async fn get_rules_handler(
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
 
+    let accounts = account::Entity::find().all(&db).await.map_err(|err| {
+        eprintln!("Error finding accounts: {:?}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
     let html = RulesTemplate {
         rules,
         categories,
+        accounts,
         menu: "rules",
     };
     Ok(axum::response::Html(html.render().unwrap()))
@@ -102,6 +235,26 @@ pub async fn edit_rule(
     Extension(db): Extension<DatabaseConnection>,
     Form(form): Form<RuleForm>,
 ) -> impl IntoResponse {
+    let patterns_case_insensitive = form.patterns_case_insensitive.is_some();
+    let patterns = match parse_patterns_field(&form.patterns, patterns_case_insensitive) {
+        Ok(patterns) => patterns,
+        Err(error) => return error.into_response(),
+    };
+    let amount = match parse_amount_conditions(
+        &form.amount_min,
+        &form.amount_max,
+        &form.amount_exact,
+        &form.amount_tolerance,
+        &form.amount_sign,
+    ) {
+        Ok(amount) => amount,
+        Err(error) => return error.into_response(),
+    };
+    let conditions = match parse_conditions_field(&form.conditions) {
+        Ok(conditions) => conditions,
+        Err(error) => return error.into_response(),
+    };
+
     let mut rule: rule::ActiveModel = rule::Entity::find_by_id(rule_id)
         .one(&db)
         .await
@@ -113,13 +266,14 @@ pub async fn edit_rule(
     rule.label = Set(form.label);
     rule.percentage = Set(form.percentage);
     rule.category_id = Set(form.category_id);
-    rule.regexpr = Set(Some(form.regexpr));
+    rule.patterns = Set(rule::Patterns(patterns));
+    rule.patterns_case_insensitive = Set(patterns_case_insensitive);
     rule.date_start = Set(if form.date_start.trim().is_empty() {
         None
     } else {
         match NaiveDate::parse_from_str(&form.date_start, "%Y-%m-%d") {
             Ok(d) => Some(d),
-            Err(_) => return StatusCode::BAD_REQUEST,
+            Err(_) => return StatusCode::BAD_REQUEST.into_response(),
         }
     });
 
@@ -128,14 +282,203 @@ pub async fn edit_rule(
     } else {
         match NaiveDate::parse_from_str(&form.date_end, "%Y-%m-%d") {
             Ok(d) => Some(d),
-            Err(_) => return StatusCode::BAD_REQUEST,
+            Err(_) => return StatusCode::BAD_REQUEST.into_response(),
         }
     });
 
+    rule.amount_min = Set(amount.min);
+    rule.amount_max = Set(amount.max);
+    rule.amount_exact = Set(amount.exact);
+    rule.amount_tolerance = Set(amount.tolerance);
+    rule.amount_sign = Set(amount.sign);
+    rule.conditions = Set(conditions);
+    rule.priority = Set(form.priority.unwrap_or_default());
+
     let _ = rule.update(&db).await.map_err(|err| {
         eprintln!("Cannot update rule: {}", err);
         return StatusCode::INTERNAL_SERVER_ERROR;
     });
 
-    return StatusCode::OK;
+    return StatusCode::OK.into_response();
+}
+
+/// Runs a saved rule against the past transactions of an account without
+/// changing them.
+pub async fn test_rule_handler(
+    Path(rule_id): Path<i32>,
+    Query(query): Query<RuleTestQuery>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> impl IntoResponse {
+    let the_rule = match rule::Entity::find_by_id(rule_id).one(&db).await {
+        Ok(Some(the_rule)) => the_rule,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Errore nel recupero della regola: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    backtest_rule(&db, the_rule, query).await
+}
+
+/// Same as `test_rule_handler` for a rule not saved yet.
+pub async fn test_draft_rule_handler(
+    Query(query): Query<RuleTestQuery>,
+    Extension(db): Extension<DatabaseConnection>,
+    Json(draft): Json<DraftRule>,
+) -> impl IntoResponse {
+    let conditions = match draft.conditions.as_ref().map(serde_json::to_string) {
+        Some(Ok(json)) => Some(json),
+        Some(Err(e)) => {
+            eprintln!("Errore nella scrittura delle condizioni: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+        None => None,
+    };
+    let the_rule = rule::Model {
+        id: draft.id.unwrap_or_default(),
+        name: draft.name,
+        label: draft.label,
+        percentage: draft.percentage,
+        category_id: draft.category_id,
+        patterns: rule::Patterns(
+            draft
+                .patterns
+                .into_iter()
+                .filter(|pattern| !pattern.trim().is_empty())
+                .collect(),
+        ),
+        patterns_case_insensitive: draft.patterns_case_insensitive,
+        date_start: draft.date_start,
+        date_end: draft.date_end,
+        amount_min: draft.amount_min,
+        amount_max: draft.amount_max,
+        amount_exact: draft.amount_exact,
+        amount_tolerance: draft.amount_tolerance,
+        amount_sign: draft.amount_sign.filter(|s| !s.is_empty()),
+        conditions,
+        priority: draft.priority,
+    };
+    if let Err(error) = validate_rule(&the_rule) {
+        return error.into_response();
+    }
+
+    backtest_rule(&db, the_rule, query).await
+}
+
+/// Lists the transactions of the account the rule matches, with how many of
+/// them would change category and how many would end up in a conflict with
+/// the other active rules under the account conflict policy.
+async fn backtest_rule(
+    db: &DatabaseConnection,
+    the_rule: rule::Model,
+    query: RuleTestQuery,
+) -> axum::response::Response {
+    let uncategorized_only = match query.scope.as_deref() {
+        None | Some("") | Some("all") => false,
+        Some("uncategorized") => true,
+        Some(scope) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Ambito non valido '{}': usa all o uncategorized", scope),
+            )
+                .into_response()
+        }
+    };
+
+    match account::Entity::find_by_id(query.account_id).one(db).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Errore nel recupero account: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+
+    let result: Result<RuleTestReport, sea_orm::DbErr> = async {
+        let mut select = transaction::Entity::find()
+            .filter(transaction::Column::AccountId.eq(query.account_id))
+            .order_by_desc(transaction::Column::Date);
+        if uncategorized_only {
+            select = select.filter(transaction::Column::CategoryId.is_null());
+        }
+        let transactions = select.all(db).await?;
+
+        let categories: HashMap<i32, String> = category::Entity::find()
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|c| (c.id, c.category))
+            .collect();
+
+        let other_rules = get_active_rules(db, query.account_id)
+            .await?
+            .into_iter()
+            .filter(|r| r.id != the_rule.id)
+            .collect();
+        let policy = get_conflict_policy(db, query.account_id).await?;
+
+        let to_db_err = |e: regex::Error| sea_orm::DbErr::Custom(e.to_string());
+        let matcher = RuleMatcher::new(vec![the_rule.clone()]).map_err(to_db_err)?;
+        let others = RuleMatcher::new(other_rules).map_err(to_db_err)?;
+
+        let mut report = RuleTestReport {
+            matched: 0,
+            category_changes: 0,
+            conflicts: 0,
+            transactions: Vec::new(),
+        };
+        for transaction in transactions {
+            if matcher.matching(&transaction).is_empty() {
+                continue;
+            }
+
+            let other_matching = others.matching(&transaction);
+            let other_names = other_matching.iter().map(|r| r.name.clone()).collect();
+            let mut matching = vec![the_rule.clone()];
+            matching.extend(other_matching);
+            let winning_rule = match choose_rule(matching, &policy) {
+                RuleChoice::Winner { rule, .. } => Some(rule.name),
+                RuleChoice::Conflict(_) => {
+                    report.conflicts += 1;
+                    None
+                }
+                RuleChoice::NoMatch => None,
+            };
+
+            let category_changes = transaction.category_id != Some(the_rule.category_id);
+            if category_changes {
+                report.category_changes += 1;
+            }
+            report.matched += 1;
+            report.transactions.push(RuleTestMatch {
+                id: transaction.id,
+                date: transaction.date.date().to_string(),
+                description: transaction.description,
+                value: transaction.value,
+                category: transaction
+                    .category_id
+                    .and_then(|id| categories.get(&id).cloned())
+                    .unwrap_or_else(|| "-".to_string()),
+                category_changes,
+                other_rules: other_names,
+                winning_rule,
+            });
+        }
+
+        Ok(report)
+    }
+    .await;
+
+    match result {
+        Ok(report) => (StatusCode::OK, Json(report)).into_response(),
+        Err(e) => {
+            eprintln!("Errore nel test della regola: {:?}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Errore nel test della regola",
+            )
+                .into_response()
+        }
+    }
 }
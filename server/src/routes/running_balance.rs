@@ -0,0 +1,224 @@
+use chrono::NaiveDate;
+use serde::Serialize;
+
+/// A movement as needed to follow the running balance of a statement.
+pub struct BalanceEntry<'a> {
+    pub date: NaiveDate,
+    pub description: &'a str,
+    pub value: f64,
+    /// Balance stated by the bank after this movement, when the file has one.
+    pub balance: Option<f64>,
+}
+
+/// A row whose stated balance doesn't follow from the previous one plus its
+/// amount: a movement is missing or duplicated between the two.
+#[derive(Serialize)]
+pub struct BalanceBreak {
+    pub date: NaiveDate,
+    pub description: String,
+    pub value: f64,
+    pub expected_balance: f64,
+    pub stated_balance: f64,
+    pub difference: f64,
+}
+
+/// Compares the opening balance of a statement with the balance verified by
+/// the previous import of the account.
+#[derive(Serialize)]
+pub struct ContinuityCheck {
+    pub verified_balance: f64,
+    pub verified_date: NaiveDate,
+    pub opening_balance: f64,
+    pub difference: f64,
+    pub continuous: bool,
+}
+
+pub struct RunningBalance {
+    pub breaks: Vec<BalanceBreak>,
+    /// Last stated balance in chronological order, with its date.
+    pub closing: Option<(NaiveDate, f64)>,
+    /// Entry indexes in chronological order.
+    order: Vec<usize>,
+}
+
+fn differs(a: f64, b: f64) -> bool {
+    (a - b).abs() >= 0.005
+}
+
+/// Follows the balance along `order`, resynchronizing on the stated balance
+/// after every break so a single missing movement is reported once.
+fn find_breaks(entries: &[BalanceEntry], order: &[usize]) -> Vec<BalanceBreak> {
+    let mut breaks = Vec::new();
+    let mut last = None;
+
+    for &i in order {
+        let entry = &entries[i];
+        last = match (last, entry.balance) {
+            (Some(previous), Some(stated)) => {
+                let expected = previous + entry.value;
+                if differs(expected, stated) {
+                    breaks.push(BalanceBreak {
+                        date: entry.date,
+                        description: entry.description.to_string(),
+                        value: entry.value,
+                        expected_balance: expected,
+                        stated_balance: stated,
+                        difference: stated - expected,
+                    });
+                }
+                Some(stated)
+            }
+            (Some(previous), None) => Some(previous + entry.value),
+            (None, stated) => stated,
+        };
+    }
+
+    breaks
+}
+
+/// Checks the running balance of a statement. Banks list movements oldest or
+/// newest first, and same-day movements in no particular order, so both
+/// orders of the file are tried and the one that reconciles best is kept.
+pub fn reconcile(entries: &[BalanceEntry]) -> RunningBalance {
+    let forward: Vec<usize> = (0..entries.len()).collect();
+    let backward: Vec<usize> = forward.iter().rev().copied().collect();
+
+    let forward_breaks = find_breaks(entries, &forward);
+    let backward_breaks = find_breaks(entries, &backward);
+
+    let newest_first = match (entries.first(), entries.last()) {
+        (Some(first), Some(last)) => first.date > last.date,
+        _ => false,
+    };
+    let use_backward = backward_breaks.len() < forward_breaks.len()
+        || (backward_breaks.len() == forward_breaks.len() && newest_first);
+
+    let (order, breaks) = if use_backward {
+        (backward, backward_breaks)
+    } else {
+        (forward, forward_breaks)
+    };
+
+    let closing = order
+        .iter()
+        .rev()
+        .find_map(|&i| entries[i].balance.map(|balance| (entries[i].date, balance)));
+
+    RunningBalance {
+        breaks,
+        closing,
+        order,
+    }
+}
+
+impl RunningBalance {
+    /// The statement is expected to start where the last verified import
+    /// ended: the balance before its first movement after the verified date
+    /// must be the verified balance. Movements up to that date overlap with
+    /// the previous import and are not checked.
+    pub fn continuity(
+        &self,
+        entries: &[BalanceEntry],
+        verified_balance: f64,
+        verified_date: NaiveDate,
+    ) -> Option<ContinuityCheck> {
+        let mut movements_total = 0.0;
+        for &i in &self.order {
+            let entry = &entries[i];
+            if entry.date <= verified_date {
+                continue;
+            }
+
+            movements_total += entry.value;
+            if let Some(balance) = entry.balance {
+                let opening_balance = balance - movements_total;
+                return Some(ContinuityCheck {
+                    verified_balance,
+                    verified_date,
+                    opening_balance,
+                    difference: opening_balance - verified_balance,
+                    continuous: !differs(opening_balance, verified_balance),
+                });
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn entry(day: &str, value: f64, balance: Option<f64>) -> BalanceEntry<'static> {
+        BalanceEntry {
+            date: date(day),
+            description: "movimento",
+            value,
+            balance,
+        }
+    }
+
+    #[test]
+    fn balanced_statement_in_either_order() {
+        let oldest_first = vec![
+            entry("2025-01-02", -45.3, Some(954.7)),
+            entry("2025-01-03", 1500.0, None),
+            entry("2025-01-05", -120.0, Some(2334.7)),
+        ];
+        let result = reconcile(&oldest_first);
+        assert!(result.breaks.is_empty());
+        assert_eq!(result.closing, Some((date("2025-01-05"), 2334.7)));
+
+        let newest_first: Vec<BalanceEntry> = oldest_first.into_iter().rev().collect();
+        let result = reconcile(&newest_first);
+        assert!(result.breaks.is_empty());
+        assert_eq!(result.closing, Some((date("2025-01-05"), 2334.7)));
+    }
+
+    #[test]
+    fn missing_movement_is_reported_once() {
+        let entries = vec![
+            entry("2025-01-02", -45.3, Some(954.7)),
+            // A 100.00 debit is missing before this row.
+            entry("2025-01-03", 1500.0, Some(2354.7)),
+            entry("2025-01-05", -120.0, Some(2234.7)),
+        ];
+        let result = reconcile(&entries);
+        assert_eq!(result.breaks.len(), 1);
+        let gap = &result.breaks[0];
+        assert_eq!(gap.date, date("2025-01-03"));
+        assert!((gap.expected_balance - 2454.7).abs() < 0.001);
+        assert!((gap.difference + 100.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn continuity_with_the_verified_balance() {
+        let entries = vec![
+            entry("2025-01-31", -20.0, Some(1000.0)),
+            entry("2025-02-01", -45.3, None),
+            entry("2025-02-03", 1500.0, Some(2454.7)),
+        ];
+        let result = reconcile(&entries);
+
+        let check = result
+            .continuity(&entries, 1000.0, date("2025-01-31"))
+            .unwrap();
+        assert!(check.continuous);
+        assert!(check.difference.abs() < 0.001);
+
+        let check = result
+            .continuity(&entries, 980.0, date("2025-01-31"))
+            .unwrap();
+        assert!(!check.continuous);
+        assert!((check.difference - 20.0).abs() < 0.001);
+
+        assert!(result
+            .continuity(&entries, 2454.7, date("2025-02-03"))
+            .is_none());
+    }
+}
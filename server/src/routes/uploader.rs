@@ -1,142 +1,2082 @@
+use anyhow::anyhow;
 use axum::{
     extract::{Multipart, Path},
     http::StatusCode,
     response::IntoResponse,
     Extension, Json,
 };
-use calamine::{Reader, Xls, Xlsx};
-use chrono::{Duration, NaiveDate};
+use calamine::{Data, Range, Reader, Xls, Xlsx};
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
 use csv::ReaderBuilder;
+use encoding_rs::Encoding;
+use regex::{Regex, RegexBuilder};
 use sea_orm::{
-    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, ConnectionTrait, DatabaseConnection,
+    EntityTrait, QueryFilter, QueryOrder, TransactionTrait,
 };
 use serde::Serialize;
-use std::io::Cursor;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet},
+    io::Cursor,
+};
+
+use crate::{
+    database::{
+        category, import_batch, import_file, import_profile, import_row, settings, transaction,
+    },
+    routes::{
+        account_imports::restore_verified_balance,
+        backup::import_profile_to_dto,
+        running_balance::{reconcile, BalanceBreak, BalanceEntry, ContinuityCheck},
+    },
+    statement_store,
+};
+
+#[derive(Serialize)]
+pub struct ImportSummary {
+    pub batch_id: i32,
+    pub review_url: String,
+    pub rows_staged: usize,
+    pub duplicates_skipped: usize,
+    pub duplicates_flagged: usize,
+    flagged_rows: Vec<FlaggedRow>,
+    balance_checks: Vec<BalanceCheck>,
+    /// Rows where the running balance stated by the bank doesn't add up.
+    balance_breaks: Vec<BalanceBreak>,
+    /// Whether the statement starts from the last verified balance of the
+    /// account, when both are known.
+    continuity: Option<ContinuityCheck>,
+    rejected_rows: Vec<RejectedRow>,
+}
+
+/// Returned instead of the summary when rows were rejected and the upload
+/// asked to abort: nothing has been staged.
+#[derive(Serialize)]
+struct AbortedImport {
+    message: String,
+    rejected_rows: Vec<RejectedRow>,
+}
+
+/// A row of the file that could not be turned into a transaction, with the
+/// cells as they were read so the user can find and fix it.
+#[derive(Serialize)]
+struct RejectedRow {
+    file: String,
+    row: usize,
+    cells: Vec<String>,
+    reason: String,
+}
+
+impl RejectedRow {
+    fn new<S: AsRef<str>>(row: usize, cells: &[S], reason: String) -> Self {
+        RejectedRow {
+            file: String::new(),
+            row,
+            cells: cells.iter().map(|c| c.as_ref().to_string()).collect(),
+            reason,
+        }
+    }
+}
+
+/// What to do with the valid rows of an upload when some rows are rejected.
+#[derive(Clone, Copy, PartialEq)]
+enum RowErrorPolicy {
+    /// Nothing is staged, the user fixes the file and uploads it again.
+    Abort,
+    /// Valid rows are staged, rejected ones are only reported.
+    Skip,
+}
+
+impl RowErrorPolicy {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "abort" => Some(RowErrorPolicy::Abort),
+            "skip" => Some(RowErrorPolicy::Skip),
+            _ => None,
+        }
+    }
+}
+
+/// An imported row that looks like an existing transaction without being an
+/// exact copy of it. It is staged anyway and reported for review.
+#[derive(Serialize)]
+struct FlaggedRow {
+    date: NaiveDate,
+    value: f64,
+    description: String,
+    existing_transaction_id: i32,
+}
+
+/// Compares the balances declared by a statement with the entries it carries,
+/// so a truncated or partially parsed file doesn't go unnoticed.
+#[derive(Serialize)]
+struct BalanceCheck {
+    statement_id: Option<String>,
+    opening_balance: f64,
+    closing_balance: f64,
+    entries_total: f64,
+    difference: f64,
+    balanced: bool,
+}
+
+impl BalanceCheck {
+    fn new(
+        statement_id: Option<String>,
+        opening_balance: f64,
+        closing_balance: f64,
+        entries_total: f64,
+    ) -> Self {
+        let difference = closing_balance - (opening_balance + entries_total);
+        BalanceCheck {
+            statement_id,
+            opening_balance,
+            closing_balance,
+            entries_total,
+            difference,
+            balanced: difference.abs() < 0.005,
+        }
+    }
+}
+
+fn aborted_import(rejected_rows: Vec<RejectedRow>) -> axum::response::Response {
+    (
+        StatusCode::UNPROCESSABLE_ENTITY,
+        Json(AbortedImport {
+            message: format!("Import annullato: {} righe non valide", rejected_rows.len()),
+            rejected_rows,
+        }),
+    )
+        .into_response()
+}
+
+/// A statement file of an import, kept by the statement store.
+struct StatementFile {
+    filename: String,
+    file_hash: String,
+}
+
+#[derive(Default)]
+struct ParsedStatement {
+    transactions: Vec<TransactionData>,
+    balance_checks: Vec<BalanceCheck>,
+    rejected_rows: Vec<RejectedRow>,
+}
+
+#[derive(Default)]
+pub struct TransactionData {
+    pub description: String,
+    pub value: f64,
+    pub date: NaiveDate,
+    pub external_id: Option<String>,
+    pub label: Option<String>,
+    /// `macro_category:category` as found in the file, resolved against the
+    /// categories table before inserting.
+    pub category: Option<String>,
+    pub currency: Option<String>,
+    /// Running balance stated by the bank after this movement.
+    pub balance: Option<f64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StatementFormat {
+    Csv,
+    Xlsx,
+    Xls,
+    Ofx,
+    Camt,
+    Mt940,
+    Qif,
+    Pdf,
+}
+
+/// Guesses the statement format from the file content, since banks are not
+/// consistent with extensions (`.qfx`, `.ofx`, `.xls` files that are xlsx...).
+pub fn detect_format(data: &[u8]) -> Option<StatementFormat> {
+    const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+    const OLE_MAGIC: &[u8] = &[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+
+    if data.starts_with(b"%PDF-") {
+        return Some(StatementFormat::Pdf);
+    }
+    if data.starts_with(ZIP_MAGIC) {
+        return Some(StatementFormat::Xlsx);
+    }
+    if data.starts_with(OLE_MAGIC) {
+        return Some(StatementFormat::Xls);
+    }
+
+    if data.starts_with(&[0xFF, 0xFE]) || data.starts_with(&[0xFE, 0xFF]) {
+        return Some(StatementFormat::Csv);
+    }
+
+    let head = &data[..data.len().min(4096)];
+    if head.contains(&0) {
+        return None;
+    }
+
+    let head = String::from_utf8_lossy(head).to_uppercase();
+    let first_line = head.trim_start_matches('\u{feff}').trim_start();
+    if first_line.starts_with("!TYPE:") || first_line.starts_with("!ACCOUNT") {
+        return Some(StatementFormat::Qif);
+    }
+    if head.contains("CAMT.053")
+        || head.contains("CAMT.054")
+        || head.contains("<BKTOCSTMRSTMT>")
+        || head.contains("<BKTOCSTMRDBTCDTNTFCTN>")
+    {
+        return Some(StatementFormat::Camt);
+    }
+    if head.contains("OFXHEADER") || head.contains("<OFX>") {
+        return Some(StatementFormat::Ofx);
+    }
+    if head.contains(":20:") && (head.contains(":60F:") || head.contains(":60M:")) {
+        return Some(StatementFormat::Mt940);
+    }
+
+    Some(StatementFormat::Csv)
+}
+
+/// A profile pinned to a tabular format rejects other tabular files, which
+/// usually means a statement from another bank was uploaded to the account.
+/// Self-describing formats are always accepted.
+fn profile_accepts(profile: &import_profile::Model, format: StatementFormat) -> bool {
+    let expected = match format {
+        StatementFormat::Csv => import_profile::FORMAT_CSV,
+        StatementFormat::Xlsx => import_profile::FORMAT_XLSX,
+        StatementFormat::Xls => import_profile::FORMAT_XLS,
+        StatementFormat::Pdf => import_profile::FORMAT_PDF,
+        _ => return true,
+    };
+
+    profile.format == import_profile::FORMAT_AUTO || profile.format == expected
+}
+
+pub fn excel_number_to_date(excel_number: &str) -> Option<NaiveDate> {
+    let n: i64 = excel_number.parse().ok()?;
+    let base_date = NaiveDate::from_ymd_opt(1900, 1, 1)?;
+    Some(base_date + Duration::days(n - 2))
+}
+
+/// Turns the separator stored in a profile into the single byte the csv reader
+/// expects. Tabs can be written as `\t` or `tab` since they are hard to type in
+/// a form field.
+pub fn separator_byte(value: &str) -> Option<u8> {
+    match value {
+        "\\t" | "tab" | "\t" => Some(b'\t'),
+        v if v.len() == 1 && v.is_ascii() => Some(v.as_bytes()[0]),
+        _ => None,
+    }
+}
+
+struct CsvDialect {
+    delimiter: u8,
+    quote: u8,
+    encoding: &'static Encoding,
+    decimal_separator: String,
+    thousands_separator: String,
+    date_format: String,
+}
+
+impl CsvDialect {
+    fn from_profile(profile: &import_profile::Model) -> anyhow::Result<Self> {
+        let delimiter = separator_byte(&profile.delimiter)
+            .ok_or_else(|| anyhow!("Invalid delimiter '{}'", profile.delimiter))?;
+        let quote = separator_byte(&profile.quote_char)
+            .ok_or_else(|| anyhow!("Invalid quote char '{}'", profile.quote_char))?;
+        let encoding = Encoding::for_label(profile.encoding.trim().as_bytes())
+            .ok_or_else(|| anyhow!("Unknown encoding '{}'", profile.encoding))?;
+
+        Ok(CsvDialect {
+            delimiter,
+            quote,
+            encoding,
+            decimal_separator: profile.decimal_separator.clone(),
+            thousands_separator: profile.thousands_separator.clone(),
+            date_format: profile.date_format.clone(),
+        })
+    }
+}
+
+/// A column of a tabular statement: the header names it may appear under and
+/// the position to fall back to when none of them is in the header row.
+struct ColumnSpec {
+    aliases: Vec<String>,
+    index: Option<usize>,
+}
+
+impl ColumnSpec {
+    /// `aliases` is the `|` separated list stored in the profile, e.g.
+    /// `Data contabile|Data operazione`.
+    fn new(aliases: &str, index: Option<i32>) -> Self {
+        ColumnSpec {
+            aliases: aliases
+                .split('|')
+                .map(normalize_header)
+                .filter(|a| !a.is_empty())
+                .collect(),
+            index: index.and_then(|i| usize::try_from(i).ok()),
+        }
+    }
+
+    fn find_by_name<S: AsRef<str>>(&self, header: &[S]) -> Option<usize> {
+        self.aliases.iter().find_map(|alias| {
+            header
+                .iter()
+                .position(|h| normalize_header(h.as_ref()) == *alias)
+        })
+    }
+
+    fn resolve<S: AsRef<str>>(&self, header: &[S]) -> Option<usize> {
+        self.find_by_name(header).or(self.index)
+    }
+}
+
+pub fn normalize_header(value: &str) -> String {
+    value
+        .trim_start_matches('\u{feff}')
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Where each field lives in a CSV or spreadsheet statement, as configured in
+/// the account profile.
+struct ColumnMapping {
+    date: ColumnSpec,
+    description: ColumnSpec,
+    value: ColumnSpec,
+    debit: ColumnSpec,
+    credit: ColumnSpec,
+    direction: ColumnSpec,
+    amount_layout: AmountLayout,
+    /// Credit card exports list charges as positive amounts.
+    invert_sign: bool,
+    currency: ColumnSpec,
+    counterparty: ColumnSpec,
+    reference: ColumnSpec,
+    balance: ColumnSpec,
+    starter_string: String,
+    worksheet: String,
+    end_marker: String,
+    stop_at_blank_row: bool,
+    skip_patterns: Vec<Regex>,
+}
+
+/// What to do with a row found after the header.
+#[derive(PartialEq)]
+enum RowAction {
+    Read,
+    Skip,
+    Stop,
+}
+
+/// Compiles the skip patterns of a profile, one case-insensitive regular
+/// expression per line.
+pub fn skip_patterns(value: &str) -> Result<Vec<Regex>, regex::Error> {
+    value
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| RegexBuilder::new(line).case_insensitive(true).build())
+        .collect()
+}
+
+/// Compiles the PDF line patterns of a profile, like the skip patterns, and
+/// checks that each one captures the fields every transaction needs.
+pub fn pdf_line_patterns(value: &str) -> anyhow::Result<Vec<Regex>> {
+    let patterns = skip_patterns(value)?;
+    for pattern in &patterns {
+        for group in ["date", "description", "amount"] {
+            if !pattern.capture_names().any(|name| name == Some(group)) {
+                return Err(anyhow!("Pattern '{}' has no '{}' group", pattern, group));
+            }
+        }
+    }
+    Ok(patterns)
+}
+
+fn debit_markers(value: &str) -> Vec<String> {
+    value
+        .split('|')
+        .map(|m| m.trim().to_lowercase())
+        .filter(|m| !m.is_empty())
+        .collect()
+}
+
+/// How the signed amount of a row is spread over the columns.
+enum AmountLayout {
+    /// One column with negative debits and positive credits.
+    Signed,
+    /// A debit ("Dare") and a credit ("Avere") column, one of them empty.
+    Split,
+    /// An always-positive amount plus a column telling debits from credits.
+    Direction { debit_markers: Vec<String> },
+}
+
+enum AmountColumns {
+    Signed(usize),
+    Split {
+        debit: usize,
+        credit: usize,
+    },
+    Direction {
+        value: usize,
+        direction: usize,
+        debit_markers: Vec<String>,
+    },
+}
+
+/// Column positions once the header row has been read.
+struct ResolvedColumns {
+    date: usize,
+    description: usize,
+    amount: AmountColumns,
+    invert_sign: bool,
+    currency: Option<usize>,
+    counterparty: Option<usize>,
+    reference: Option<usize>,
+    balance: Option<usize>,
+}
+
+impl ColumnMapping {
+    fn from_profile(profile: &import_profile::Model) -> anyhow::Result<Self> {
+        Ok(ColumnMapping {
+            date: ColumnSpec::new(&profile.date_header, Some(profile.date_index)),
+            description: ColumnSpec::new(
+                &profile.description_header,
+                Some(profile.description_index),
+            ),
+            value: ColumnSpec::new(&profile.value_header, Some(profile.value_index)),
+            debit: ColumnSpec::new(&profile.debit_header, profile.debit_index),
+            credit: ColumnSpec::new(&profile.credit_header, profile.credit_index),
+            direction: ColumnSpec::new(&profile.direction_header, profile.direction_index),
+            amount_layout: match profile.amount_layout.as_str() {
+                import_profile::AMOUNT_SPLIT => AmountLayout::Split,
+                import_profile::AMOUNT_DIRECTION => AmountLayout::Direction {
+                    debit_markers: debit_markers(&profile.debit_markers),
+                },
+                _ => AmountLayout::Signed,
+            },
+            invert_sign: profile.invert_sign,
+            currency: ColumnSpec::new(&profile.currency_header, profile.currency_index),
+            counterparty: ColumnSpec::new(&profile.counterparty_header, profile.counterparty_index),
+            reference: ColumnSpec::new(&profile.reference_header, profile.reference_index),
+            balance: ColumnSpec::new(&profile.balance_header, profile.balance_index),
+            starter_string: profile.starter_string.clone(),
+            worksheet: profile.worksheet.trim().to_string(),
+            end_marker: profile.end_marker.clone(),
+            stop_at_blank_row: profile.stop_at_blank_row,
+            skip_patterns: skip_patterns(&profile.skip_patterns)?,
+        })
+    }
+
+    /// Keeps footers, totals and sections that are not movements (opening
+    /// balance lines, pending movements...) out of the import.
+    fn row_action<S: AsRef<str>>(&self, values: &[S]) -> RowAction {
+        if values.iter().all(|v| v.as_ref().trim().is_empty()) {
+            return if self.stop_at_blank_row {
+                RowAction::Stop
+            } else {
+                RowAction::Skip
+            };
+        }
+
+        if !self.end_marker.is_empty()
+            && values.iter().any(|v| v.as_ref().contains(&self.end_marker))
+        {
+            return RowAction::Stop;
+        }
+
+        if values
+            .iter()
+            .any(|v| self.skip_patterns.iter().any(|p| p.is_match(v.as_ref())))
+        {
+            return RowAction::Skip;
+        }
+
+        RowAction::Read
+    }
+
+    /// The header row is the first one containing `starter_string` or, when
+    /// no starter string is set, the first one where the date, description
+    /// and amount headers can all be found by name.
+    fn is_header_row<S: AsRef<str>>(&self, values: &[S]) -> bool {
+        if self.starter_string.is_empty() && !self.date.aliases.is_empty() {
+            let amount_found = match self.amount_layout {
+                AmountLayout::Split => {
+                    self.debit.find_by_name(values).is_some()
+                        && self.credit.find_by_name(values).is_some()
+                }
+                _ => self.value.find_by_name(values).is_some(),
+            };
+            return self.date.find_by_name(values).is_some()
+                && self.description.find_by_name(values).is_some()
+                && amount_found;
+        }
+
+        values
+            .iter()
+            .any(|v| v.as_ref().contains(&self.starter_string))
+    }
+
+    fn resolve<S: AsRef<str>>(&self, header: &[S]) -> anyhow::Result<ResolvedColumns> {
+        let required = |name: &str, spec: &ColumnSpec| {
+            spec.resolve(header)
+                .ok_or_else(|| anyhow!("Column '{}' not found in the header row", name))
+        };
+
+        let amount = match self.amount_layout {
+            AmountLayout::Signed => AmountColumns::Signed(required("value", &self.value)?),
+            AmountLayout::Split => AmountColumns::Split {
+                debit: required("debit", &self.debit)?,
+                credit: required("credit", &self.credit)?,
+            },
+            AmountLayout::Direction { ref debit_markers } => AmountColumns::Direction {
+                value: required("value", &self.value)?,
+                direction: required("direction", &self.direction)?,
+                debit_markers: debit_markers.clone(),
+            },
+        };
+
+        Ok(ResolvedColumns {
+            date: required("date", &self.date)?,
+            description: required("description", &self.description)?,
+            amount,
+            invert_sign: self.invert_sign,
+            currency: self.currency.resolve(header),
+            counterparty: self.counterparty.resolve(header),
+            reference: self.reference.resolve(header),
+            balance: self.balance.resolve(header),
+        })
+    }
+}
+
+impl ResolvedColumns {
+    /// Builds the signed amount of a row from the configured layout. Debits
+    /// are always negative, whatever sign the bank writes them with.
+    fn amount<S: AsRef<str>>(
+        &self,
+        values: &[S],
+        parse_number: impl Fn(&str) -> Option<f64>,
+    ) -> Option<f64> {
+        let cell = |col: usize| values.get(col).map(|v| v.as_ref().trim());
+
+        let value = match &self.amount {
+            AmountColumns::Signed(value) => parse_number(cell(*value)?)?,
+            AmountColumns::Split { debit, credit } => {
+                let debit = cell(*debit).filter(|v| !v.is_empty());
+                let credit = cell(*credit).filter(|v| !v.is_empty());
+                if debit.is_none() && credit.is_none() {
+                    return None;
+                }
+                let debit = debit.map(&parse_number).unwrap_or(Some(0.0))?;
+                let credit = credit.map(&parse_number).unwrap_or(Some(0.0))?;
+                credit.abs() - debit.abs()
+            }
+            AmountColumns::Direction {
+                value,
+                direction,
+                debit_markers,
+            } => {
+                let value = parse_number(cell(*value)?)?.abs();
+                let direction = cell(*direction).unwrap_or_default().to_lowercase();
+                if debit_markers.contains(&direction) {
+                    -value
+                } else {
+                    value
+                }
+            }
+        };
+
+        Some(if self.invert_sign { -value } else { value })
+    }
+
+    /// Turns a data row into a transaction, or tells why it can't be one.
+    fn parse_row<S: AsRef<str>>(
+        &self,
+        values: &[S],
+        parse_date: impl Fn(&str) -> Option<NaiveDate>,
+        parse_number: impl Fn(&str) -> Option<f64>,
+    ) -> Result<TransactionData, String> {
+        let cell = |col: usize| {
+            values
+                .get(col)
+                .map(|v| v.as_ref().trim())
+                .ok_or_else(|| format!("Colonna {} mancante", col + 1))
+        };
+
+        let raw_date = cell(self.date)?;
+        let date = parse_date(raw_date).ok_or_else(|| format!("Data non valida '{}'", raw_date))?;
+        let description = cell(self.description)?.to_string();
+        let value = self
+            .amount(values, &parse_number)
+            .ok_or_else(|| "Importo mancante o non numerico".to_string())?;
+
+        let mut transaction = TransactionData {
+            description,
+            value,
+            date,
+            ..Default::default()
+        };
+        self.apply_optional(values, &mut transaction, parse_number);
+        Ok(transaction)
+    }
+
+    /// Fills the optional fields of a parsed row. The counterparty is kept in
+    /// the description so rules can match on it, and the bank reference
+    /// becomes the external id used for duplicate detection.
+    fn apply_optional<S: AsRef<str>>(
+        &self,
+        values: &[S],
+        transaction: &mut TransactionData,
+        parse_number: impl Fn(&str) -> Option<f64>,
+    ) {
+        let cell = |col: Option<usize>| {
+            col.and_then(|c| values.get(c))
+                .map(|v| v.as_ref().trim())
+                .filter(|v| !v.is_empty())
+        };
+
+        if let Some(counterparty) = cell(self.counterparty) {
+            if !transaction.description.contains(counterparty) {
+                transaction.description = format!("{} - {}", counterparty, transaction.description);
+            }
+        }
+        transaction.currency = cell(self.currency).map(|c| c.to_uppercase());
+        transaction.external_id = cell(self.reference).map(|r| r.to_string());
+        transaction.balance = cell(self.balance).and_then(parse_number);
+    }
+}
+
+pub fn parse_amount(raw: &str, decimal_separator: &str, thousands_separator: &str) -> Option<f64> {
+    let mut cleaned: String = raw
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '€' && *c != '+')
+        .collect();
+
+    if !thousands_separator.is_empty() {
+        cleaned = cleaned.replace(thousands_separator, "");
+    }
+    if !decimal_separator.is_empty() && decimal_separator != "." {
+        cleaned = cleaned.replace(decimal_separator, ".");
+    }
+
+    cleaned.parse().ok()
+}
+
+fn parse_date(raw: &str, date_format: &str) -> Option<NaiveDate> {
+    let raw = raw.trim();
+
+    NaiveDate::parse_from_str(raw, date_format)
+        .or_else(|_| NaiveDateTime::parse_from_str(raw, date_format).map(|dt| dt.date()))
+        .ok()
+}
+
+async fn process_csv(
+    data: &[u8],
+    mapping: &ColumnMapping,
+    dialect: &CsvDialect,
+) -> anyhow::Result<ParsedStatement> {
+    let mut parsed = ParsedStatement::default();
+
+    let (text, _, had_errors) = dialect.encoding.decode(data);
+    if had_errors {
+        eprintln!(
+            "Caratteri non validi per l'encoding {} nel file CSV",
+            dialect.encoding.name()
+        );
+    }
+
+    let mut rdr = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(dialect.delimiter)
+        .quote(dialect.quote)
+        .from_reader(Cursor::new(text.as_bytes()));
+
+    let parse_date = |raw: &str| parse_date(raw, &dialect.date_format);
+    let parse_number = |raw: &str| {
+        parse_amount(
+            raw,
+            &dialect.decimal_separator,
+            &dialect.thousands_separator,
+        )
+    };
+
+    let mut columns = None;
+    for (idx, result) in rdr.records().enumerate() {
+        let record = match result {
+            Ok(record) => record,
+            Err(e) => {
+                parsed.rejected_rows.push(RejectedRow::new::<&str>(
+                    idx + 1,
+                    &[],
+                    format!("Riga non leggibile: {}", e),
+                ));
+                continue;
+            }
+        };
+        let row = record
+            .position()
+            .map(|p| p.line() as usize)
+            .unwrap_or(idx + 1);
+        let values: Vec<&str> = record.iter().map(|v| v.trim()).collect();
+
+        let Some(columns) = &columns else {
+            if mapping.is_header_row(&values) {
+                columns = Some(mapping.resolve(&values)?);
+            }
+            continue;
+        };
+
+        match mapping.row_action(&values) {
+            RowAction::Read => {}
+            RowAction::Skip => continue,
+            RowAction::Stop => break,
+        }
+
+        match columns.parse_row(&values, parse_date, parse_number) {
+            Ok(transaction) => parsed.transactions.push(transaction),
+            Err(reason) => parsed
+                .rejected_rows
+                .push(RejectedRow::new(row, &values, reason)),
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// Text of a worksheet cell. Numbers are written with the decimal separator
+/// of the profile, so they read back like amounts stored as text.
+fn worksheet_cell(cell: &Data, decimal_separator: &str) -> String {
+    match cell {
+        Data::Float(_) | Data::Int(_) if !decimal_separator.is_empty() => {
+            cell.to_string().replace('.', decimal_separator)
+        }
+        _ => cell.to_string(),
+    }
+}
+
+/// Reads a worksheet with the profile mapping. Excel stores dates as serial
+/// numbers and amounts as plain numbers, whatever the cell format shows, but
+/// some exports write amounts as text in the format of the profile.
+fn process_worksheet(
+    range: &Range<Data>,
+    mapping: &ColumnMapping,
+    decimal_separator: &str,
+    thousands_separator: &str,
+) -> anyhow::Result<ParsedStatement> {
+    let mut parsed = ParsedStatement::default();
+    let first_row = range.start().map(|(row, _)| row as usize).unwrap_or(0);
+    let parse_number = |raw: &str| parse_amount(raw, decimal_separator, thousands_separator);
+
+    let mut columns = None;
+    for (idx, row) in range.rows().enumerate() {
+        let values: Vec<String> = row
+            .iter()
+            .map(|c| worksheet_cell(c, decimal_separator))
+            .collect();
+
+        let Some(columns) = &columns else {
+            if mapping.is_header_row(&values) {
+                columns = Some(mapping.resolve(&values)?);
+            }
+            continue;
+        };
+
+        match mapping.row_action(&values) {
+            RowAction::Read => {}
+            RowAction::Skip => continue,
+            RowAction::Stop => break,
+        }
+
+        match columns.parse_row(&values, excel_number_to_date, parse_number) {
+            Ok(transaction) => parsed.transactions.push(transaction),
+            Err(reason) => {
+                parsed
+                    .rejected_rows
+                    .push(RejectedRow::new(first_row + idx + 1, &values, reason))
+            }
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// The sheet named in the profile, by name or 0-based index, or the first one
+/// of the workbook.
+fn profile_worksheet<RS, R>(workbook: &mut R, worksheet: &str) -> anyhow::Result<Range<Data>>
+where
+    RS: std::io::Read + std::io::Seek,
+    R: Reader<RS>,
+    R::Error: std::error::Error + Send + Sync + 'static,
+{
+    let range = if worksheet.is_empty() {
+        workbook.worksheet_range_at(0)
+    } else if workbook.sheet_names().iter().any(|name| name == worksheet) {
+        Some(workbook.worksheet_range(worksheet))
+    } else {
+        worksheet
+            .parse()
+            .ok()
+            .and_then(|index| workbook.worksheet_range_at(index))
+    };
+
+    match range {
+        Some(range) => Ok(range?),
+        None if worksheet.is_empty() => Ok(Range::empty()),
+        None => Err(anyhow!("Worksheet '{}' not found", worksheet)),
+    }
+}
+
+async fn process_xlsx(
+    data: &[u8],
+    mapping: &ColumnMapping,
+    profile: &import_profile::Model,
+) -> anyhow::Result<ParsedStatement> {
+    let mut workbook: Xlsx<_> = Xlsx::new(Cursor::new(data))?;
+    let range = profile_worksheet(&mut workbook, &mapping.worksheet)?;
+    process_worksheet(
+        &range,
+        mapping,
+        &profile.decimal_separator,
+        &profile.thousands_separator,
+    )
+}
+
+async fn process_xls(
+    data: &[u8],
+    mapping: &ColumnMapping,
+    profile: &import_profile::Model,
+) -> anyhow::Result<ParsedStatement> {
+    let mut workbook: Xls<_> = Xls::new(Cursor::new(data))?;
+    let range = profile_worksheet(&mut workbook, &mapping.worksheet)?;
+    process_worksheet(
+        &range,
+        mapping,
+        &profile.decimal_separator,
+        &profile.thousands_separator,
+    )
+}
+
+/// How movements are read from the text of a PDF statement: each line
+/// matching one of the patterns is a transaction.
+struct PdfLayout {
+    patterns: Vec<Regex>,
+    date_format: String,
+    decimal_separator: String,
+    thousands_separator: String,
+    debit_markers: Vec<String>,
+    invert_sign: bool,
+}
+
+impl PdfLayout {
+    fn from_profile(profile: &import_profile::Model) -> anyhow::Result<Self> {
+        Ok(PdfLayout {
+            patterns: pdf_line_patterns(&profile.pdf_line_patterns)?,
+            date_format: profile.date_format.clone(),
+            decimal_separator: profile.decimal_separator.clone(),
+            thousands_separator: profile.thousands_separator.clone(),
+            debit_markers: debit_markers(&profile.debit_markers),
+            invert_sign: profile.invert_sign,
+        })
+    }
+
+    /// Turns the groups captured from a line into a transaction. Debits are
+    /// negative when the amount carries a minus, even a trailing one
+    /// (`12,50-`), or when the `sign` group holds a debit marker.
+    fn parse_line(&self, captures: &regex::Captures) -> Result<TransactionData, String> {
+        let group = |name: &str| {
+            captures
+                .name(name)
+                .map(|m| m.as_str().trim())
+                .filter(|v| !v.is_empty())
+        };
+        let parse_number =
+            |raw: &str| parse_amount(raw, &self.decimal_separator, &self.thousands_separator);
+
+        let raw_date = group("date").unwrap_or_default();
+        let date = parse_date(raw_date, &self.date_format)
+            .ok_or_else(|| format!("Data non valida '{}'", raw_date))?;
+
+        let raw_amount = group("amount").unwrap_or_default();
+        let (number, trailing_minus) = match raw_amount.strip_suffix('-') {
+            Some(number) => (number, true),
+            None => (raw_amount, false),
+        };
+        let mut value =
+            parse_number(number).ok_or_else(|| format!("Importo non valido '{}'", raw_amount))?;
+        let debit_sign = group("sign")
+            .map(|s| s.to_lowercase())
+            .is_some_and(|s| s == "-" || self.debit_markers.contains(&s));
+        if trailing_minus || debit_sign {
+            value = -value.abs();
+        }
+        if self.invert_sign {
+            value = -value;
+        }
+
+        Ok(TransactionData {
+            description: group("description")
+                .unwrap_or_default()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" "),
+            value,
+            date,
+            external_id: group("reference").map(|r| r.to_string()),
+            currency: group("currency").map(|c| c.to_uppercase()),
+            balance: group("balance").and_then(parse_number),
+            ..Default::default()
+        })
+    }
+}
+
+/// Reads the text layer of a PDF statement. Lines that match no pattern
+/// (headers, page footers, totals) are ignored, while the end marker and the
+/// skip patterns of the profile apply as for the other formats. Scanned
+/// statements have no text layer and yield no movements.
+async fn process_pdf(
+    data: &[u8],
+    mapping: &ColumnMapping,
+    layout: &PdfLayout,
+) -> anyhow::Result<ParsedStatement> {
+    let mut parsed = ParsedStatement::default();
+
+    // Extraction is CPU bound and panics on some malformed files, so it runs
+    // on a blocking thread where a panic only fails this upload.
+    let data = data.to_vec();
+    let text =
+        tokio::task::spawn_blocking(move || pdf_extract::extract_text_from_mem(&data)).await??;
+
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        match mapping.row_action(&[line]) {
+            RowAction::Read => {}
+            RowAction::Skip => continue,
+            RowAction::Stop => break,
+        }
+
+        let Some(captures) = layout.patterns.iter().find_map(|p| p.captures(line)) else {
+            continue;
+        };
+
+        match layout.parse_line(&captures) {
+            Ok(transaction) => parsed.transactions.push(transaction),
+            Err(reason) => parsed
+                .rejected_rows
+                .push(RejectedRow::new(idx + 1, &[line], reason)),
+        }
+    }
+
+    Ok(parsed)
+}
+
+fn ofx_unescape(value: &str) -> String {
+    value
+        .trim()
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Parses both OFX 1.x (SGML, leaf elements are never closed) and OFX 2.x
+/// (XML) by walking the tags in order: every `<STMTTRN>` aggregate becomes a
+/// transaction, and leaf values are read up to the next tag.
+async fn process_ofx(data: &[u8]) -> anyhow::Result<ParsedStatement> {
+    let mut parsed = ParsedStatement::default();
+
+    let header = String::from_utf8_lossy(&data[..data.len().min(1024)]).to_uppercase();
+    let encoding = if [
+        "CHARSET:1252",
+        "CHARSET:8859-1",
+        "WINDOWS-1252",
+        "ISO-8859-1",
+    ]
+    .iter()
+    .any(|label| header.contains(label))
+    {
+        encoding_rs::WINDOWS_1252
+    } else {
+        encoding_rs::UTF_8
+    };
+    let (text, _, _) = encoding.decode(data);
+
+    let tag_re = Regex::new(r"<(/?)([A-Za-z0-9.]+)[^>]*>([^<]*)")?;
+
+    let mut current: Option<HashMap<String, String>> = None;
+    let mut entry = 0;
+    for caps in tag_re.captures_iter(&text) {
+        let closing = !caps[1].is_empty();
+        let tag = caps[2].to_uppercase();
+
+        match (closing, tag.as_str()) {
+            (false, "STMTTRN") => {
+                entry += 1;
+                current = Some(HashMap::new());
+            }
+            (true, "STMTTRN") => {
+                let Some(fields) = current.take() else {
+                    continue;
+                };
+                let field = |name: &str| fields.get(name).cloned().unwrap_or_default();
+
+                let reject = |reason: String| {
+                    let cells: Vec<String> = ["DTPOSTED", "TRNAMT", "NAME", "MEMO", "FITID"]
+                        .iter()
+                        .map(|name| format!("{}: {}", name, field(name)))
+                        .collect();
+                    RejectedRow::new(entry, &cells, reason)
+                };
+
+                let posted = field("DTPOSTED");
+                let Some(date) = posted
+                    .get(..8)
+                    .and_then(|d| NaiveDate::parse_from_str(d, "%Y%m%d").ok())
+                else {
+                    parsed
+                        .rejected_rows
+                        .push(reject(format!("DTPOSTED non valido '{}'", posted)));
+                    continue;
+                };
+
+                let amount = field("TRNAMT");
+                let Ok(value) = amount.replace(',', ".").parse::<f64>() else {
+                    parsed
+                        .rejected_rows
+                        .push(reject(format!("TRNAMT non valido '{}'", amount)));
+                    continue;
+                };
+
+                let name = field("NAME");
+                let memo = field("MEMO");
+                let description = if memo.is_empty() || name.contains(&memo) {
+                    name
+                } else if name.is_empty() || memo.contains(&name) {
+                    memo
+                } else {
+                    format!("{} {}", name, memo)
+                };
+
+                let fitid = field("FITID");
+
+                parsed.transactions.push(TransactionData {
+                    description,
+                    value,
+                    date,
+                    external_id: if fitid.is_empty() { None } else { Some(fitid) },
+                    ..Default::default()
+                });
+            }
+            (false, _) => {
+                if let Some(fields) = current.as_mut() {
+                    let value = ofx_unescape(&caps[3]);
+                    if !value.is_empty() {
+                        fields.entry(tag).or_insert(value);
+                    }
+                }
+            }
+            (true, _) => {}
+        }
+    }
+
+    Ok(parsed)
+}
+
+fn xml_child<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|c| c.tag_name().name() == name)
+}
+
+fn xml_path<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    path: &[&str],
+) -> Option<roxmltree::Node<'a, 'input>> {
+    path.iter()
+        .try_fold(node, |node, name| xml_child(node, name))
+}
+
+fn xml_text<'a>(node: roxmltree::Node<'a, '_>, path: &[&str]) -> Option<&'a str> {
+    xml_path(node, path)
+        .and_then(|n| n.text())
+        .map(|t| t.trim())
+        .filter(|t| !t.is_empty())
+}
+
+/// Reads an `Amt` + `CdtDbtInd` pair, which is how CAMT expresses signed
+/// amounts for both entries and balances.
+fn camt_signed_amount(node: roxmltree::Node) -> anyhow::Result<f64> {
+    let amount = xml_text(node, &["Amt"]).ok_or_else(|| anyhow!("Missing Amt"))?;
+    let value: f64 = amount
+        .parse()
+        .map_err(|_| anyhow!("Invalid Amt '{}'", amount))?;
+
+    match xml_text(node, &["CdtDbtInd"]) {
+        Some("DBIT") => Ok(-value),
+        Some("CRDT") => Ok(value),
+        other => Err(anyhow!("Invalid CdtDbtInd {:?}", other)),
+    }
+}
+
+fn camt_date(node: roxmltree::Node) -> Option<NaiveDate> {
+    let raw = xml_text(node, &["Dt"]).or_else(|| xml_text(node, &["DtTm"]))?;
+    raw.get(..10)
+        .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+}
+
+fn camt_description(entry: roxmltree::Node) -> String {
+    let credit = xml_text(entry, &["CdtDbtInd"]) == Some("CRDT");
+    let mut counterparty = None;
+    let mut remittance: Vec<&str> = Vec::new();
+
+    for details in entry
+        .descendants()
+        .filter(|n| n.tag_name().name() == "TxDtls")
+    {
+        if counterparty.is_none() {
+            // Older versions put the name right under Dbtr/Cdtr, newer ones
+            // wrap it in a Pty element.
+            let party = if credit { "Dbtr" } else { "Cdtr" };
+            counterparty = xml_text(details, &["RltdPties", party, "Nm"])
+                .or_else(|| xml_text(details, &["RltdPties", party, "Pty", "Nm"]));
+        }
+
+        if let Some(info) = xml_child(details, "RmtInf") {
+            remittance.extend(
+                info.children()
+                    .filter(|c| c.tag_name().name() == "Ustrd")
+                    .filter_map(|c| c.text())
+                    .map(|t| t.trim())
+                    .filter(|t| !t.is_empty()),
+            );
+        }
+    }
+
+    if remittance.is_empty() {
+        if let Some(info) = xml_text(entry, &["AddtlNtryInf"]) {
+            remittance.push(info);
+        }
+    }
+
+    let remittance = remittance.join(" ");
+    match counterparty {
+        Some(name) if !remittance.is_empty() => format!("{} - {}", name, remittance),
+        Some(name) => name.to_string(),
+        None => remittance,
+    }
+}
+
+/// Parses ISO 20022 camt.053 statements and camt.054 notifications. Only
+/// booked entries are imported; camt.053 opening and closing balances are
+/// checked against the imported entries.
+async fn process_camt(data: &[u8]) -> anyhow::Result<ParsedStatement> {
+    let mut parsed = ParsedStatement::default();
+
+    let text = String::from_utf8_lossy(data);
+    let document = roxmltree::Document::parse(text.trim_start_matches('\u{feff}'))?;
+
+    for statement in document
+        .descendants()
+        .filter(|n| matches!(n.tag_name().name(), "Stmt" | "Ntfctn"))
+    {
+        let mut opening_balance = None;
+        let mut closing_balance = None;
+        let mut entries_total = 0.0;
+
+        for balance in statement
+            .children()
+            .filter(|n| n.tag_name().name() == "Bal")
+        {
+            match xml_text(balance, &["Tp", "CdOrPrtry", "Cd"]) {
+                Some("OPBD") | Some("PRCD") => opening_balance = Some(camt_signed_amount(balance)?),
+                Some("CLBD") => closing_balance = Some(camt_signed_amount(balance)?),
+                _ => {}
+            }
+        }
+
+        for entry in statement
+            .children()
+            .filter(|n| n.tag_name().name() == "Ntry")
+        {
+            let status = xml_text(entry, &["Sts", "Cd"]).or_else(|| xml_text(entry, &["Sts"]));
+            if status.is_some_and(|s| s != "BOOK") {
+                continue;
+            }
+
+            let reject = |reason: String| {
+                let cells: Vec<&str> = [
+                    xml_text(entry, &["BookgDt", "Dt"]),
+                    xml_text(entry, &["Amt"]),
+                    xml_text(entry, &["CdtDbtInd"]),
+                    xml_text(entry, &["AcctSvcrRef"]),
+                ]
+                .iter()
+                .map(|c| c.unwrap_or_default())
+                .collect();
+                let line = document.text_pos_at(entry.range().start).row as usize;
+                RejectedRow::new(line, &cells, reason)
+            };
+
+            let value = match camt_signed_amount(entry) {
+                Ok(value) => value,
+                Err(e) => {
+                    parsed.rejected_rows.push(reject(e.to_string()));
+                    continue;
+                }
+            };
+            let Some(date) = xml_child(entry, "BookgDt")
+                .or_else(|| xml_child(entry, "ValDt"))
+                .and_then(camt_date)
+            else {
+                parsed
+                    .rejected_rows
+                    .push(reject("Movimento senza data contabile".to_string()));
+                continue;
+            };
+
+            let external_id = xml_text(entry, &["AcctSvcrRef"])
+                .or_else(|| xml_text(entry, &["NtryRef"]))
+                .map(|r| r.to_string());
+
+            entries_total += value;
+            parsed.transactions.push(TransactionData {
+                description: camt_description(entry),
+                value,
+                date,
+                external_id,
+                ..Default::default()
+            });
+        }
+
+        if let (Some(opening), Some(closing)) = (opening_balance, closing_balance) {
+            parsed.balance_checks.push(BalanceCheck::new(
+                xml_text(statement, &["Id"]).map(|id| id.to_string()),
+                opening,
+                closing,
+                entries_total,
+            ));
+        }
+    }
+
+    Ok(parsed)
+}
+
+fn mt940_date(raw: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(raw, "%y%m%d").ok()
+}
+
+fn mt940_amount(raw: &str) -> Option<f64> {
+    raw.replace(',', ".").parse().ok()
+}
+
+/// Reads a :60F:/:62F: style balance: `C250131EUR1234,56`.
+fn mt940_balance(raw: &str) -> anyhow::Result<f64> {
+    let mark = raw.get(..1).unwrap_or_default();
+    let amount = raw
+        .get(10..)
+        .and_then(mt940_amount)
+        .ok_or_else(|| anyhow!("Invalid balance '{}'", raw))?;
+
+    match mark {
+        "C" => Ok(amount),
+        "D" => Ok(-amount),
+        _ => Err(anyhow!("Invalid balance mark in '{}'", raw)),
+    }
+}
+
+/// Extracts a readable description from a :86: field. Structured fields use
+/// `?NN` subfields (`?20`-`?29` remittance, `?32`-`?33` counterparty), anything
+/// else is taken as free text.
+fn mt940_description(raw: &str) -> String {
+    if !raw.contains("?20") {
+        return raw.split_whitespace().collect::<Vec<_>>().join(" ");
+    }
+
+    // Subfields are wrapped on 65 characters lines, the line breaks are not
+    // part of the content.
+    let raw = raw.replace('\n', "");
+    let mut remittance = Vec::new();
+    let mut counterparty = Vec::new();
+    for part in raw.split('?').skip(1) {
+        // `?` may be followed by anything, codes are two ASCII digits.
+        let (code, value) = match part.get(..2) {
+            Some(code) => (code, &part[2..]),
+            None => ("", part),
+        };
+        let value = value.trim();
+        if value.is_empty() {
+            continue;
+        }
+        match code {
+            "20" | "21" | "22" | "23" | "24" | "25" | "26" | "27" | "28" | "29" => {
+                remittance.push(value)
+            }
+            "32" | "33" => counterparty.push(value),
+            _ => {}
+        }
+    }
+
+    let remittance = remittance.join(" ");
+    if counterparty.is_empty() {
+        remittance
+    } else {
+        format!("{} - {}", counterparty.join(""), remittance)
+    }
+}
+
+struct Mt940Statement {
+    id: Option<String>,
+    opening_balance: Option<f64>,
+    closing_balance: Option<f64>,
+    entries_total: f64,
+}
+
+fn close_mt940_statement(statement: Option<Mt940Statement>, parsed: &mut ParsedStatement) {
+    if let Some(Mt940Statement {
+        id,
+        opening_balance: Some(opening),
+        closing_balance: Some(closing),
+        entries_total,
+    }) = statement
+    {
+        parsed
+            .balance_checks
+            .push(BalanceCheck::new(id, opening, closing, entries_total));
+    }
+}
+
+/// Parses SWIFT MT940 statements. Fields are split on `:tag:` markers at the
+/// beginning of a line, each :61: movement takes its description from the
+/// following :86: and the :60F:/:62F: balances are checked per statement.
+async fn process_mt940(data: &[u8]) -> anyhow::Result<ParsedStatement> {
+    let mut parsed = ParsedStatement::default();
+
+    let text = match std::str::from_utf8(data) {
+        Ok(text) => text.to_string(),
+        Err(_) => encoding_rs::WINDOWS_1252.decode(data).0.into_owned(),
+    };
+
+    let field_re = Regex::new(r"^:([0-9]{2}[A-Z]?):(.*)$")?;
+    let movement_re = Regex::new(
+        r"^(?P<date>\d{6})(?P<entry>\d{4})?(?P<mark>R?[CD])[A-Z]?(?P<amount>\d+,\d*)(?P<kind>[NSF][A-Z0-9]{3})(?P<reference>[^/]*)(?://(?P<bank_ref>\S+))?",
+    )?;
+
+    let mut fields: Vec<(String, String, usize)> = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim_end().trim_start_matches("{4:");
+        if line.starts_with('{') || line == "-}" || line == "-" {
+            continue;
+        }
+        if let Some(caps) = field_re.captures(line) {
+            fields.push((caps[1].to_string(), caps[2].to_string(), idx + 1));
+        } else if let Some((_, value, _)) = fields.last_mut() {
+            value.push('\n');
+            value.push_str(line);
+        }
+    }
+
+    let mut statement: Option<Mt940Statement> = None;
+    // A :86: describes the movement only right after its :61:. One following
+    // a rejected :61:, or a statement level one after the balances or a new
+    // :20:, must not end up on the previous movement.
+    let mut last_was_movement = false;
+    for (tag, content, line) in fields {
+        let follows_movement = std::mem::replace(&mut last_was_movement, false);
+        match tag.as_str() {
+            "20" => {
+                close_mt940_statement(statement.take(), &mut parsed);
+                statement = Some(Mt940Statement {
+                    id: Some(content.trim().to_string()),
+                    opening_balance: None,
+                    closing_balance: None,
+                    entries_total: 0.0,
+                });
+            }
+            "60F" | "60M" => {
+                if let Some(statement) = statement.as_mut() {
+                    statement.opening_balance = Some(mt940_balance(content.trim())?);
+                }
+            }
+            "62F" | "62M" => {
+                if let Some(statement) = statement.as_mut() {
+                    statement.closing_balance = Some(mt940_balance(content.trim())?);
+                }
+            }
+            "61" => {
+                let first_line = content.lines().next().unwrap_or_default();
+                let movement = movement_re
+                    .captures(first_line)
+                    .ok_or("Campo :61: non valido");
+                let movement = movement.and_then(|caps| {
+                    let date = mt940_date(&caps["date"]).ok_or("Data non valida nel campo :61:")?;
+                    let amount =
+                        mt940_amount(&caps["amount"]).ok_or("Importo non valido nel campo :61:")?;
+                    Ok((caps, date, amount))
+                });
+                let (caps, date, amount) = match movement {
+                    Ok(movement) => movement,
+                    Err(reason) => {
+                        let cells: Vec<&str> = content.lines().collect();
+                        parsed.rejected_rows.push(RejectedRow::new(
+                            line,
+                            &cells,
+                            reason.to_string(),
+                        ));
+                        continue;
+                    }
+                };
+                last_was_movement = true;
+                // Reversals carry the mark of the movement they cancel.
+                let value = match &caps["mark"] {
+                    "C" | "RD" => amount,
+                    _ => -amount,
+                };
 
-use crate::database::{settings, transaction};
+                if let Some(statement) = statement.as_mut() {
+                    statement.entries_total += value;
+                }
 
-#[derive(Serialize)]
-struct ImportSummary {
-    rows_imported: usize,
+                let reference = caps["reference"].trim();
+                let supplementary = content.lines().skip(1).collect::<Vec<_>>().join(" ");
+                parsed.transactions.push(TransactionData {
+                    description: if supplementary.trim().is_empty() {
+                        reference.to_string()
+                    } else {
+                        supplementary.trim().to_string()
+                    },
+                    value,
+                    date,
+                    external_id: caps.name("bank_ref").map(|r| r.as_str().to_string()),
+                    ..Default::default()
+                });
+            }
+            "86" if follows_movement => {
+                if let Some(last) = parsed.transactions.last_mut() {
+                    let description = mt940_description(&content);
+                    if !description.is_empty() {
+                        last.description = description;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    close_mt940_statement(statement, &mut parsed);
+
+    Ok(parsed)
 }
 
-struct TransactionData {
-    description: String,
-    value: f64,
-    date: NaiveDate,
+/// QIF has no way to declare its date layout; Quicken writes `M/D/Y` with an
+/// apostrophe before two digit years, other tools use ISO or dotted dates.
+fn parse_qif_date(raw: &str) -> Option<NaiveDate> {
+    let normalized = raw.trim().replace('\'', "/").replace(' ', "");
+
+    // Two digit years first, `%Y` would happily read `25` as year 25.
+    ["%m/%d/%y", "%m/%d/%Y", "%Y-%m-%d", "%d.%m.%y", "%d.%m.%Y"]
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(&normalized, format).ok())
 }
 
-fn excel_number_to_date(excel_number: &str) -> Option<NaiveDate> {
-    let n: i64 = excel_number.parse().ok()?;
-    let base_date = NaiveDate::from_ymd_opt(1900, 1, 1)?;
-    Some(base_date + Duration::days(n - 2))
+fn parse_qif_amount(raw: &str) -> Option<f64> {
+    let raw = raw.trim();
+    let (decimal_separator, thousands_separator) = match (raw.rfind('.'), raw.rfind(',')) {
+        (Some(dot), Some(comma)) if comma > dot => (",", "."),
+        (None, Some(comma)) if raw.len() - comma == 3 => (",", ""),
+        _ => (".", ","),
+    };
+
+    parse_amount(raw, decimal_separator, thousands_separator)
 }
 
-async fn process_csv(
-    data: &[u8],
-    _date_idx: usize,
-    _description_idx: usize,
-    _value_idx: usize,
-    _starter_string: String,
-) -> anyhow::Result<Vec<TransactionData>> {
-    let transactions = Vec::new();
+/// Parses bank, cash and credit card sections of a Quicken Interchange Format
+/// file. The payee becomes the description, the memo the label and `L` lines
+/// the category; account lists, category lists and splits are ignored.
+async fn process_qif(data: &[u8]) -> anyhow::Result<ParsedStatement> {
+    let mut parsed = ParsedStatement::default();
 
-    let mut rdr = ReaderBuilder::new()
-        .has_headers(false)
-        .from_reader(Cursor::new(data));
+    let text = match std::str::from_utf8(data) {
+        Ok(text) => text.to_string(),
+        Err(_) => encoding_rs::WINDOWS_1252.decode(data).0.into_owned(),
+    };
+
+    let mut in_transactions = false;
+    let mut fields: HashMap<char, String> = HashMap::new();
+    // Raw lines of the current record and where it starts, for error reports.
+    let mut record: Vec<&str> = Vec::new();
+    let mut record_start = 0;
+
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim_start_matches('\u{feff}').trim_end();
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.starts_with('!') {
+            let header = line.to_uppercase();
+            in_transactions = ["!TYPE:BANK", "!TYPE:CASH", "!TYPE:CCARD", "!TYPE:OTH"]
+                .iter()
+                .any(|t| header.starts_with(t));
+            fields.clear();
+            record.clear();
+            continue;
+        }
+
+        if !in_transactions {
+            continue;
+        }
+
+        let mut chars = line.chars();
+        let code = chars.next().unwrap_or_default();
+        let value = chars.as_str().trim();
+
+        if code != '^' {
+            if record.is_empty() {
+                record_start = idx + 1;
+            }
+            record.push(line);
+            // Split lines repeat the same codes, only the first one matters.
+            fields.entry(code).or_insert_with(|| value.to_string());
+            continue;
+        }
+
+        if fields.is_empty() {
+            continue;
+        }
+
+        let raw_date = fields.get(&'D').cloned().unwrap_or_default();
+        let raw_amount = fields
+            .get(&'T')
+            .or_else(|| fields.get(&'U'))
+            .cloned()
+            .unwrap_or_default();
+        let (date, value) = match (parse_qif_date(&raw_date), parse_qif_amount(&raw_amount)) {
+            (Some(date), Some(value)) => (date, value),
+            (date, _) => {
+                let reason = match date {
+                    None => format!("Data non valida '{}'", raw_date),
+                    Some(_) => format!("Importo non valido '{}'", raw_amount),
+                };
+                parsed
+                    .rejected_rows
+                    .push(RejectedRow::new(record_start, &record, reason));
+                fields.clear();
+                record.clear();
+                continue;
+            }
+        };
 
-    for (_idx, _result) in rdr.records().enumerate() {
-        // let record = result?;
-        todo!();
+        let payee = fields.remove(&'P').filter(|p| !p.is_empty());
+        let memo = fields.remove(&'M').filter(|m| !m.is_empty());
+        let (description, label) = match (payee, memo) {
+            (Some(payee), memo) => (payee, memo),
+            (None, Some(memo)) => (memo, None),
+            (None, None) => (String::new(), None),
+        };
+
+        // Transfers are written as `[Other account]`, they are not categories.
+        let category = fields
+            .remove(&'L')
+            .filter(|l| !l.is_empty() && !l.starts_with('['));
+
+        parsed.transactions.push(TransactionData {
+            description,
+            value,
+            date,
+            label,
+            category,
+            ..Default::default()
+        });
+        fields.clear();
+        record.clear();
     }
 
-    Ok(transactions)
+    Ok(parsed)
 }
 
-async fn process_xlsx(
-    data: &[u8],
-    date_idx: usize,
-    description_idx: usize,
-    value_idx: usize,
-    starter_string: String,
-) -> anyhow::Result<Vec<TransactionData>> {
-    let mut transactions = Vec::new();
-    let cursor = Cursor::new(data);
-    let mut workbook: Xlsx<_> = Xlsx::new(cursor)?;
-
-    if let Some(Ok(range)) = workbook.worksheet_range_at(0) {
-        let mut found = false;
-        for row in range.rows() {
-            let values: Vec<String> = row.iter().map(|c| c.to_string()).collect();
-
-            if !found {
-                if values.iter().any(|v| v.contains(&starter_string)) {
-                    found = true;
-                    continue;
-                } else {
-                    continue;
-                }
+/// How many days apart two movements with the same amount can be and still be
+/// suspected to be the same one (booking vs value date, weekends...).
+const NEAR_DUPLICATE_DAYS: i64 = 3;
+
+fn normalize_description(description: &str) -> String {
+    description
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn amount_cents(value: f64) -> i64 {
+    (value * 100.0).round() as i64
+}
+
+#[derive(Clone, Hash, PartialEq, Eq)]
+struct Fingerprint {
+    date: NaiveDate,
+    amount_cents: i64,
+    description: String,
+}
+
+impl Fingerprint {
+    fn new(date: NaiveDate, value: f64, description: &str) -> Self {
+        Fingerprint {
+            date,
+            amount_cents: amount_cents(value),
+            description: normalize_description(description),
+        }
+    }
+}
+
+pub enum DuplicateStatus {
+    Unique,
+    Duplicate,
+    NearDuplicate(i32),
+}
+
+/// Fingerprints of the transactions already stored for an account around the
+/// period being imported. Fingerprints are counted rather than just collected,
+/// so two identical movements in a statement are both skipped only if both are
+/// already in the ledger.
+#[derive(Default)]
+pub struct DuplicateIndex {
+    external_ids: HashSet<String>,
+    fingerprints: HashMap<Fingerprint, usize>,
+    existing: Vec<(i32, Fingerprint)>,
+}
+
+impl DuplicateIndex {
+    pub async fn load<C: ConnectionTrait>(
+        db: &C,
+        account_id: i32,
+        transactions: &[TransactionData],
+    ) -> Result<Self, sea_orm::DbErr> {
+        let mut index = DuplicateIndex::default();
+
+        let (Some(first), Some(last)) = (
+            transactions.iter().map(|t| t.date).min(),
+            transactions.iter().map(|t| t.date).max(),
+        ) else {
+            return Ok(index);
+        };
+
+        let external_ids: Vec<String> = transactions
+            .iter()
+            .filter_map(|t| t.external_id.clone())
+            .collect();
+        if !external_ids.is_empty() {
+            index.external_ids = transaction::Entity::find()
+                .filter(transaction::Column::AccountId.eq(account_id))
+                .filter(transaction::Column::ExternalId.is_in(external_ids))
+                .all(db)
+                .await?
+                .into_iter()
+                .filter_map(|t| t.external_id)
+                .collect();
+        }
+
+        let start = (first - Duration::days(NEAR_DUPLICATE_DAYS)).and_hms_opt(0, 0, 0);
+        let end = (last + Duration::days(NEAR_DUPLICATE_DAYS)).and_hms_opt(23, 59, 59);
+        let existing = transaction::Entity::find()
+            .filter(transaction::Column::AccountId.eq(account_id))
+            .filter(transaction::Column::Date.between(start, end))
+            .all(db)
+            .await?;
+
+        for transaction in existing {
+            index.record(&transaction);
+        }
+
+        Ok(index)
+    }
+
+    /// Adds a transaction already in the ledger.
+    pub fn record(&mut self, transaction: &transaction::Model) {
+        let fingerprint = Fingerprint::new(
+            transaction.date.date(),
+            transaction.value,
+            &transaction.description,
+        );
+        *self.fingerprints.entry(fingerprint.clone()).or_default() += 1;
+        self.existing.push((transaction.id, fingerprint));
+    }
+
+    /// Checks a parsed row and records it, so the same external id showing up
+    /// twice in one file is only imported once.
+    pub fn check(&mut self, transaction: &TransactionData) -> DuplicateStatus {
+        if let Some(external_id) = &transaction.external_id {
+            if !self.external_ids.insert(external_id.clone()) {
+                return DuplicateStatus::Duplicate;
             }
+        }
 
-            let date = excel_number_to_date(&values[date_idx]).unwrap();
-            let description = values[description_idx].clone();
-            let value: f64 = values[value_idx]
-                .replace(',', ".")
-                .parse()
-                .expect("Not a Number");
+        let fingerprint = Fingerprint::new(
+            transaction.date,
+            transaction.value,
+            &transaction.description,
+        );
+        if let Some(count) = self.fingerprints.get_mut(&fingerprint) {
+            if *count > 0 {
+                *count -= 1;
+                return DuplicateStatus::Duplicate;
+            }
+        }
 
-            transactions.push(TransactionData {
-                description: description,
-                value: value,
-                date: date,
-            });
+        self.existing
+            .iter()
+            .find(|(_, existing)| {
+                existing.amount_cents == fingerprint.amount_cents
+                    && (existing.date == fingerprint.date
+                        || (existing.description == fingerprint.description
+                            && (existing.date - fingerprint.date).num_days().abs()
+                                <= NEAR_DUPLICATE_DAYS))
+            })
+            .map_or(DuplicateStatus::Unique, |(id, _)| {
+                DuplicateStatus::NearDuplicate(*id)
+            })
+    }
+}
+
+/// Maps `macro_category:category` (case insensitive) to the category id, for
+/// formats that carry a category.
+pub async fn load_category_lookup<C: ConnectionTrait>(
+    db: &C,
+    transactions: &[TransactionData],
+) -> Result<HashMap<String, i32>, sea_orm::DbErr> {
+    if transactions.iter().all(|t| t.category.is_none()) {
+        return Ok(HashMap::new());
+    }
+
+    Ok(category::Entity::find()
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|c| {
+            (
+                format!("{}:{}", c.macro_category, c.category).to_lowercase(),
+                c.id,
+            )
+        })
+        .collect())
+}
+
+/// Stores the parsed rows as a staged import batch for the user to review,
+/// linked to the statement files they come from and to a snapshot of the
+/// profile used to parse them. Exact duplicates are staged already dropped,
+/// near-duplicates are kept and flagged; nothing reaches the `transactions`
+/// table until the batch is committed.
+async fn stage_import<C: ConnectionTrait + TransactionTrait>(
+    db: &C,
+    account_id: i32,
+    file_hash: &str,
+    files: &[StatementFile],
+    profile: Option<&import_profile::Model>,
+    parsed: ParsedStatement,
+) -> anyhow::Result<ImportSummary> {
+    let mut duplicates = DuplicateIndex::load(db, account_id, &parsed.transactions).await?;
+    let categories = load_category_lookup(db, &parsed.transactions).await?;
+    let profile_snapshot = profile
+        .map(|profile| serde_json::to_string(&import_profile_to_dto(profile.clone())))
+        .transpose()?;
+    let filename = files
+        .iter()
+        .map(|f| f.filename.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut balance_breaks = Vec::new();
+    let mut continuity = None;
+    if parsed.transactions.iter().any(|t| t.balance.is_some()) {
+        let entries: Vec<BalanceEntry> = parsed
+            .transactions
+            .iter()
+            .map(|t| BalanceEntry {
+                date: t.date,
+                description: &t.description,
+                value: t.value,
+                balance: t.balance,
+            })
+            .collect();
+        let running_balance = reconcile(&entries);
+
+        let account_settings = settings::Entity::find()
+            .filter(settings::Column::AccountId.eq(account_id))
+            .one(db)
+            .await?;
+        if let Some(settings::Model {
+            verified_balance: Some(verified_balance),
+            verified_balance_date: Some(verified_date),
+            ..
+        }) = account_settings
+        {
+            continuity = running_balance.continuity(&entries, verified_balance, verified_date);
         }
+        balance_breaks = running_balance.breaks;
+    }
+
+    let mut duplicates_skipped = 0;
+    let mut flagged_rows = Vec::new();
+    let mut rows = Vec::new();
+
+    let txn = db.begin().await?;
+
+    let batch = import_batch::ActiveModel {
+        account_id: Set(account_id),
+        filename: Set(filename),
+        status: Set(import_batch::STATUS_STAGED.to_string()),
+        created_at: Set(Utc::now().naive_utc()),
+        file_hash: Set(file_hash.to_string()),
+        rows_total: Set(parsed.transactions.len() as i32),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+
+    let import_files: Vec<import_file::ActiveModel> = files
+        .iter()
+        .enumerate()
+        .map(|(position, file)| import_file::ActiveModel {
+            batch_id: Set(batch.id),
+            position: Set(position as i32),
+            filename: Set(file.filename.clone()),
+            file_hash: Set(file.file_hash.clone()),
+            profile_snapshot: Set(profile_snapshot.clone()),
+            ..Default::default()
+        })
+        .collect();
+    if !import_files.is_empty() {
+        import_file::Entity::insert_many(import_files)
+            .exec(&txn)
+            .await?;
+    }
+
+    for (position, transaction) in parsed.transactions.into_iter().enumerate() {
+        let (duplicate_status, duplicate_of) = match duplicates.check(&transaction) {
+            DuplicateStatus::Duplicate => {
+                duplicates_skipped += 1;
+                (import_row::DUPLICATE, None)
+            }
+            DuplicateStatus::NearDuplicate(existing_transaction_id) => {
+                flagged_rows.push(FlaggedRow {
+                    date: transaction.date,
+                    value: transaction.value,
+                    description: transaction.description.clone(),
+                    existing_transaction_id,
+                });
+                (import_row::NEAR_DUPLICATE, Some(existing_transaction_id))
+            }
+            DuplicateStatus::Unique => (import_row::UNIQUE, None),
+        };
+
+        rows.push(import_row::ActiveModel {
+            batch_id: Set(batch.id),
+            position: Set(position as i32),
+            date: Set(transaction.date),
+            description: Set(transaction.description),
+            value: Set(transaction.value),
+            label: Set(transaction.label.unwrap_or_default()),
+            category_id: Set(transaction
+                .category
+                .and_then(|c| categories.get(&c.to_lowercase()).copied())),
+            external_id: Set(transaction.external_id),
+            duplicate_status: Set(duplicate_status.to_string()),
+            duplicate_of: Set(duplicate_of),
+            dropped: Set(duplicate_status == import_row::DUPLICATE),
+            currency: Set(transaction.currency),
+            balance: Set(transaction.balance),
+            ..Default::default()
+        });
+    }
+
+    let rows_staged = rows.len() - duplicates_skipped;
+    if !rows.is_empty() {
+        import_row::Entity::insert_many(rows).exec(&txn).await?;
     }
 
-    Ok(transactions)
+    txn.commit().await?;
+
+    Ok(ImportSummary {
+        batch_id: batch.id,
+        review_url: format!("/accounts/{}/imports/{}", account_id, batch.id),
+        rows_staged,
+        duplicates_skipped,
+        duplicates_flagged: flagged_rows.len(),
+        flagged_rows,
+        balance_checks: parsed.balance_checks,
+        balance_breaks,
+        continuity,
+        rejected_rows: parsed.rejected_rows,
+    })
 }
 
-async fn process_xls(
+/// The import profile selected in the account settings, if any.
+async fn account_profile(
+    db: &DatabaseConnection,
+    account_id: i32,
+) -> Result<Option<import_profile::Model>, sea_orm::DbErr> {
+    Ok(settings::Entity::find()
+        .filter(settings::Column::AccountId.eq(account_id))
+        .find_also_related(import_profile::Entity)
+        .one(db)
+        .await?
+        .and_then(|(_, profile)| profile))
+}
+
+/// Detects the format of a statement file and parses it with the account
+/// profile. Errors carry the status and the message for the user.
+async fn parse_statement(
+    filename: &str,
     data: &[u8],
-    date_idx: usize,
-    description_idx: usize,
-    value_idx: usize,
-    starter_string: String,
-) -> anyhow::Result<Vec<TransactionData>> {
-    let mut transactions = Vec::new();
-    let cursor = Cursor::new(data);
-    let mut workbook: Xls<_> = Xls::new(cursor)?;
-
-    if let Some(Ok(range)) = workbook.worksheet_range_at(0) {
-        let mut found = false;
-        for row in range.rows() {
-            let values: Vec<String> = row.iter().map(|c| c.to_string()).collect();
-
-            if !found {
-                if values.iter().any(|v| v.contains(&starter_string)) {
-                    found = true;
-                    continue;
-                } else {
-                    continue;
+    profile: Option<&import_profile::Model>,
+) -> Result<ParsedStatement, (StatusCode, &'static str)> {
+    let Some(format) = detect_format(data) else {
+        return Err((StatusCode::BAD_REQUEST, "Formato non supportato"));
+    };
+
+    if let Some(profile) = profile {
+        if !profile_accepts(profile, format) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Il file non corrisponde al formato del profilo di import",
+            ));
+        }
+    }
+
+    // OFX, CAMT, MT940 and QIF statements describe themselves, every other
+    // format needs the column mapping of the account's profile.
+    let parsed_statement = match (format, profile) {
+        (StatementFormat::Ofx, _) => process_ofx(data).await,
+        (StatementFormat::Camt, _) => process_camt(data).await,
+        (StatementFormat::Mt940, _) => process_mt940(data).await,
+        (StatementFormat::Qif, _) => process_qif(data).await,
+        (_, None) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Scegliere un profilo di import nelle impostazioni dell'account",
+            ));
+        }
+        (format, Some(profile)) => {
+            let mapping = ColumnMapping::from_profile(profile).map_err(|e| {
+                eprintln!("Profilo di import non valido: {:?}", e);
+                (StatusCode::BAD_REQUEST, "Profilo di import non valido")
+            })?;
+            match format {
+                StatementFormat::Pdf => {
+                    let layout = PdfLayout::from_profile(profile).map_err(|e| {
+                        eprintln!("Profilo di import non valido: {:?}", e);
+                        (StatusCode::BAD_REQUEST, "Profilo di import non valido")
+                    })?;
+                    if layout.patterns.is_empty() {
+                        return Err((
+                            StatusCode::BAD_REQUEST,
+                            "Il profilo di import non ha pattern per le righe dei PDF",
+                        ));
+                    }
+                    process_pdf(data, &mapping, &layout).await
+                }
+                StatementFormat::Xlsx => process_xlsx(data, &mapping, profile).await,
+                StatementFormat::Xls => process_xls(data, &mapping, profile).await,
+                _ => {
+                    let dialect = CsvDialect::from_profile(profile).map_err(|e| {
+                        eprintln!("Impostazioni CSV non valide: {:?}", e);
+                        (StatusCode::BAD_REQUEST, "Impostazioni CSV non valide")
+                    })?;
+                    process_csv(data, &mapping, &dialect).await
                 }
             }
+        }
+    };
 
-            let date = excel_number_to_date(&values[date_idx]).unwrap();
-            let description = values[description_idx].clone();
-            let value: f64 = values[value_idx]
-                .replace(',', ".")
-                .parse()
-                .expect("Not a Number");
+    let mut parsed = parsed_statement.map_err(|e| {
+        eprintln!("Errore import file {}: {:?}", filename, e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Errore import file")
+    })?;
+    for row in &mut parsed.rejected_rows {
+        row.file = filename.to_string();
+    }
 
-            transactions.push(TransactionData {
-                description: description,
-                value: value,
-                date: date,
-            });
+    Ok(parsed)
+}
+
+/// Writes the statement files of a batch just staged to the store. Files are
+/// stored only once their batch exists, so a refused upload leaves nothing
+/// behind; when one can't be written the batch is discarded.
+async fn store_statement_files<C: ConnectionTrait>(
+    db: &C,
+    batch_id: i32,
+    contents: &[&[u8]],
+) -> anyhow::Result<()> {
+    for data in contents {
+        if let Err(e) = statement_store::save(data).await {
+            import_batch::Entity::delete_by_id(batch_id)
+                .exec(db)
+                .await?;
+            return Err(e);
         }
     }
+    Ok(())
+}
+
+/// Parses and stages a single statement file outside of an upload, as an
+/// upload aborting on rejected rows would. The error is a message for the
+/// user, listing the rejected rows if any.
+pub async fn stage_statement_file(
+    db: &DatabaseConnection,
+    account_id: i32,
+    filename: &str,
+    data: &[u8],
+) -> Result<ImportSummary, String> {
+    let profile = account_profile(db, account_id).await.map_err(|e| {
+        eprintln!("Errore nel recupero di settings: {:?}", e);
+        "Errore recupero settings".to_string()
+    })?;
+
+    let parsed = parse_statement(filename, data, profile.as_ref())
+        .await
+        .map_err(|(_, message)| message.to_string())?;
+
+    if !parsed.rejected_rows.is_empty() {
+        let rows: Vec<String> = parsed
+            .rejected_rows
+            .iter()
+            .map(|row| format!("riga {}: {}", row.row, row.reason))
+            .collect();
+        return Err(format!(
+            "Import annullato: {} righe non valide ({})",
+            rows.len(),
+            rows.join("; ")
+        ));
+    }
+
+    let file_hash = statement_store::hash(data);
+    let files = [StatementFile {
+        filename: filename.to_string(),
+        file_hash: file_hash.clone(),
+    }];
+
+    let summary = stage_import(db, account_id, &file_hash, &files, profile.as_ref(), parsed)
+        .await
+        .map_err(|e| {
+            eprintln!("Errore nella preparazione dell'import: {:?}", e);
+            "Errore import file".to_string()
+        })?;
 
-    Ok(transactions)
+    store_statement_files(db, summary.batch_id, &[data])
+        .await
+        .map_err(|e| {
+            eprintln!("Errore salvando il file {}: {:?}", filename, e);
+            "Errore salvataggio file".to_string()
+        })?;
+    Ok(summary)
 }
 
 pub async fn upload_transaction_file(
@@ -144,89 +2084,485 @@ pub async fn upload_transaction_file(
     Extension(db): Extension<DatabaseConnection>,
     mut multipart: Multipart,
 ) -> impl IntoResponse {
-    let mut transactions = Vec::new();
-    let mut processed_transactions = 0;
+    let mut statements = ParsedStatement::default();
+    let mut files = Vec::new();
+    let mut contents = Vec::new();
+    let mut hasher = Sha256::new();
+    let mut policy = RowErrorPolicy::Abort;
 
-    let settings = settings::Entity::find()
-        .filter(settings::Column::AccountId.eq(account_id))
-        .one(&db)
-        .await
-        .expect("Errore nel recupero di settings!")
-        .unwrap();
+    let profile = match account_profile(&db, account_id).await {
+        Ok(profile) => profile,
+        Err(e) => {
+            eprintln!("Errore nel recupero di settings: {:?}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Errore recupero settings",
+            )
+                .into_response();
+        }
+    };
+
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => {
+                eprintln!("Errore nella lettura dell'upload: {:?}", e);
+                return (StatusCode::BAD_REQUEST, "Upload non valido").into_response();
+            }
+        };
 
-    let date_index: usize = settings.date_index as usize;
-    let description_index: usize = settings.description_index as usize;
-    let value_index: usize = settings.value_index as usize;
-    let starter_string: &String = &settings.starter_string;
+        if field.name() == Some("on_error") {
+            let value = field.text().await.unwrap_or_default();
+            let Some(value) = RowErrorPolicy::parse(&value) else {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    "Politica sulle righe non valide sconosciuta",
+                )
+                    .into_response();
+            };
+            policy = value;
+            continue;
+        }
 
-    while let Some(field) = multipart.next_field().await.unwrap() {
         let filename = field
             .file_name()
             .map(|s| s.to_string())
             .unwrap_or_else(|| "file".to_string());
 
-        let data = field.bytes().await.unwrap();
+        let data = match field.bytes().await {
+            Ok(data) => data,
+            Err(e) => {
+                eprintln!("Errore nella lettura del file {}: {:?}", filename, e);
+                return (StatusCode::BAD_REQUEST, "Upload non valido").into_response();
+            }
+        };
+        hasher.update(&data);
+
+        files.push(StatementFile {
+            filename: filename.clone(),
+            file_hash: statement_store::hash(&data),
+        });
 
-        let parsed_transactions = if filename.ends_with(".csv") {
-            process_csv(
-                &data,
-                date_index,
-                description_index,
-                value_index,
-                starter_string.clone(),
-            )
-            .await
-        } else if filename.ends_with(".xlsx") {
-            process_xlsx(
-                &data,
-                date_index,
-                description_index,
-                value_index,
-                starter_string.clone(),
-            )
-            .await
-        } else if filename.ends_with(".xls") {
-            process_xls(
-                &data,
-                date_index,
-                description_index,
-                value_index,
-                starter_string.clone(),
+        match parse_statement(&filename, &data, profile.as_ref()).await {
+            Ok(parsed) => {
+                statements.transactions.extend(parsed.transactions);
+                statements.balance_checks.extend(parsed.balance_checks);
+                statements.rejected_rows.extend(parsed.rejected_rows);
+            }
+            Err(response) => return response.into_response(),
+        }
+        contents.push(data);
+    }
+
+    if policy == RowErrorPolicy::Abort && !statements.rejected_rows.is_empty() {
+        return aborted_import(statements.rejected_rows);
+    }
+
+    let file_hash = format!("{:x}", hasher.finalize());
+
+    let summary = match stage_import(
+        &db,
+        account_id,
+        &file_hash,
+        &files,
+        profile.as_ref(),
+        statements,
+    )
+    .await
+    {
+        Ok(summary) => summary,
+        Err(e) => {
+            eprintln!("Errore nella preparazione dell'import: {:?}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Errore import file").into_response();
+        }
+    };
+
+    let contents: Vec<&[u8]> = contents.iter().map(|data| data.as_ref()).collect();
+    if let Err(e) = store_statement_files(&db, summary.batch_id, &contents).await {
+        eprintln!("Errore salvando i file dell'import: {:?}", e);
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Errore salvataggio file").into_response();
+    }
+
+    (StatusCode::OK, Json(summary)).into_response()
+}
+
+/// Parses the stored files of an import again with the current profile of
+/// the account, typically after fixing its column mapping. The new batch
+/// replaces the original one: a staged batch is discarded, the transactions
+/// of a committed one are deleted, its verified balance put back and it is
+/// marked as rolled back. Rejected rows abort the reprocessing and leave the
+/// original import untouched.
+pub async fn reprocess_import_batch_handler(
+    Path((account_id, batch_id)): Path<(i32, i32)>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> impl IntoResponse {
+    let batch = match import_batch::Entity::find_by_id(batch_id)
+        .filter(import_batch::Column::AccountId.eq(account_id))
+        .one(&db)
+        .await
+    {
+        Ok(Some(batch)) => batch,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Errore nel recupero dell'import: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let stored_files = match import_file::Entity::find()
+        .filter(import_file::Column::BatchId.eq(batch_id))
+        .order_by_asc(import_file::Column::Position)
+        .all(&db)
+        .await
+    {
+        Ok(files) if files.is_empty() => {
+            return (StatusCode::CONFLICT, "Import senza file originali").into_response();
+        }
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Errore nel recupero dei file dell'import: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let profile = match account_profile(&db, account_id).await {
+        Ok(profile) => profile,
+        Err(e) => {
+            eprintln!("Errore nel recupero di settings: {:?}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Errore recupero settings",
             )
-            .await
-        } else {
-            return (StatusCode::BAD_REQUEST, "Formato non supportato").into_response();
-        };
+                .into_response();
+        }
+    };
 
-        match parsed_transactions {
-            Ok(new_txts) => transactions.extend(new_txts),
+    let mut statements = ParsedStatement::default();
+    let mut files = Vec::new();
+    let mut hasher = Sha256::new();
+    for stored in stored_files {
+        let data = match statement_store::load(&stored.file_hash).await {
+            Ok(data) => data,
             Err(e) => {
-                eprintln!("Errore import file {}: {:?}", filename, e);
-                return (StatusCode::INTERNAL_SERVER_ERROR, "Errore import file").into_response();
+                eprintln!("Errore leggendo il file {}: {:?}", stored.filename, e);
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "File originale non disponibile",
+                )
+                    .into_response();
+            }
+        };
+        hasher.update(&data);
+
+        match parse_statement(&stored.filename, &data, profile.as_ref()).await {
+            Ok(parsed) => {
+                statements.transactions.extend(parsed.transactions);
+                statements.balance_checks.extend(parsed.balance_checks);
+                statements.rejected_rows.extend(parsed.rejected_rows);
             }
+            Err(response) => return response.into_response(),
         }
+        files.push(StatementFile {
+            filename: stored.filename,
+            file_hash: stored.file_hash,
+        });
     }
 
-    for transaction in transactions {
-        let model = transaction::ActiveModel {
-            account_id: Set(account_id),
-            description: Set(transaction.description),
-            value: Set(transaction.value),
-            date: Set(transaction.date.into()),
-            perc_to_exclude: Set(0.0),
-            label: Set("".to_owned()),
-            ..Default::default()
-        };
+    if !statements.rejected_rows.is_empty() {
+        return aborted_import(statements.rejected_rows);
+    }
 
-        if let Err(e) = model.insert(&db).await {
-            eprintln!("Errore nell'inserimento della transazione: {:?}", e);
-            continue;
+    let file_hash = format!("{:x}", hasher.finalize());
+
+    let result: anyhow::Result<ImportSummary> = async {
+        let txn = db.begin().await?;
+
+        match batch.status.as_str() {
+            import_batch::STATUS_STAGED => {
+                import_batch::Entity::delete_by_id(batch.id)
+                    .exec(&txn)
+                    .await?;
+            }
+            import_batch::STATUS_COMMITTED => {
+                transaction::Entity::delete_many()
+                    .filter(transaction::Column::ImportBatchId.eq(batch.id))
+                    .exec(&txn)
+                    .await?;
+                restore_verified_balance(&txn, &batch).await?;
+
+                let mut the_batch: import_batch::ActiveModel = batch.into();
+                the_batch.status = Set(import_batch::STATUS_ROLLED_BACK.to_string());
+                the_batch.update(&txn).await?;
+            }
+            _ => {}
         }
-        processed_transactions += 1;
+
+        let summary = stage_import(
+            &txn,
+            account_id,
+            &file_hash,
+            &files,
+            profile.as_ref(),
+            statements,
+        )
+        .await?;
+
+        txn.commit().await?;
+        Ok(summary)
     }
+    .await;
 
-    let summary = ImportSummary {
-        rows_imported: processed_transactions,
-    };
-    (StatusCode::OK, Json(summary)).into_response()
+    match result {
+        Ok(summary) => (StatusCode::OK, Json(summary)).into_response(),
+        Err(e) => {
+            eprintln!(
+                "Errore nella rielaborazione dell'import {}: {:?}",
+                batch_id, e
+            );
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Errore nella rielaborazione dell'import",
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::import_profiles::default_profile;
+    use sea_orm::TryIntoModel;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[tokio::test]
+    async fn ofx_sgml_transactions() {
+        let statement = "\
+OFXHEADER:100
+DATA:OFXSGML
+CHARSET:1252
+
+<OFX><BANKMSGSRSV1><STMTTRNRS><STMTRS><BANKTRANLIST>
+<STMTTRN><TRNTYPE>DEBIT<DTPOSTED>20250102120000<TRNAMT>-12.50<FITID>A1<NAME>BAR &amp; CO<MEMO>Colazione</STMTTRN>
+<STMTTRN><TRNTYPE>CREDIT<DTPOSTED>20250103<TRNAMT>1500,00<FITID>A2<NAME>STIPENDIO<MEMO>STIPENDIO</STMTTRN>
+<STMTTRN><TRNTYPE>DEBIT<DTPOSTED>2025XX03<TRNAMT>-1.00<FITID>A3<NAME>BAD</STMTTRN>
+</BANKTRANLIST></STMTRS></STMTTRNRS></BANKMSGSRSV1></OFX>
+";
+        let parsed = process_ofx(statement.as_bytes()).await.unwrap();
+
+        assert_eq!(parsed.transactions.len(), 2);
+        assert_eq!(parsed.rejected_rows.len(), 1);
+
+        let breakfast = &parsed.transactions[0];
+        assert_eq!(breakfast.date, date("2025-01-02"));
+        assert_eq!(breakfast.value, -12.5);
+        assert_eq!(breakfast.description, "BAR & CO Colazione");
+        assert_eq!(breakfast.external_id.as_deref(), Some("A1"));
+
+        let salary = &parsed.transactions[1];
+        assert_eq!(salary.value, 1500.0);
+        assert_eq!(salary.description, "STIPENDIO");
+    }
+
+    #[tokio::test]
+    async fn camt053_entries_and_balances() {
+        let statement = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.02">
+  <BkToCstmrStmt>
+    <Stmt>
+      <Id>STMT-1</Id>
+      <Bal>
+        <Tp><CdOrPrtry><Cd>OPBD</Cd></CdOrPrtry></Tp>
+        <Amt Ccy="EUR">100.00</Amt><CdtDbtInd>CRDT</CdtDbtInd>
+        <Dt><Dt>2025-01-01</Dt></Dt>
+      </Bal>
+      <Bal>
+        <Tp><CdOrPrtry><Cd>CLBD</Cd></CdOrPrtry></Tp>
+        <Amt Ccy="EUR">70.00</Amt><CdtDbtInd>CRDT</CdtDbtInd>
+        <Dt><Dt>2025-01-31</Dt></Dt>
+      </Bal>
+      <Ntry>
+        <Amt Ccy="EUR">30.00</Amt><CdtDbtInd>DBIT</CdtDbtInd>
+        <Sts>BOOK</Sts>
+        <BookgDt><Dt>2025-01-05</Dt></BookgDt>
+        <AcctSvcrRef>REF1</AcctSvcrRef>
+        <NtryDtls><TxDtls>
+          <RltdPties><Cdtr><Nm>ENEL ENERGIA</Nm></Cdtr></RltdPties>
+          <RmtInf><Ustrd>Bolletta gennaio</Ustrd></RmtInf>
+        </TxDtls></NtryDtls>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">5.00</Amt><CdtDbtInd>DBIT</CdtDbtInd>
+        <Sts>PDNG</Sts>
+        <BookgDt><Dt>2025-01-30</Dt></BookgDt>
+      </Ntry>
+    </Stmt>
+  </BkToCstmrStmt>
+</Document>"#;
+        let parsed = process_camt(statement.as_bytes()).await.unwrap();
+
+        assert_eq!(parsed.transactions.len(), 1);
+        let bill = &parsed.transactions[0];
+        assert_eq!(bill.date, date("2025-01-05"));
+        assert_eq!(bill.value, -30.0);
+        assert_eq!(bill.description, "ENEL ENERGIA - Bolletta gennaio");
+        assert_eq!(bill.external_id.as_deref(), Some("REF1"));
+
+        assert_eq!(parsed.balance_checks.len(), 1);
+        assert!(parsed.balance_checks[0].balanced);
+    }
+
+    #[test]
+    fn worksheet_amounts_as_numbers_or_text() {
+        let mut profile = default_profile("Excel");
+        profile.id = Set(1);
+        profile.date_header = Set("Data".to_string());
+        profile.description_header = Set("Descrizione".to_string());
+        profile.value_header = Set("Importo".to_string());
+        profile.balance_header = Set("Saldo".to_string());
+        let profile = profile.try_into_model().unwrap();
+        let mapping = ColumnMapping::from_profile(&profile).unwrap();
+
+        let mut range = Range::new((0, 0), (3, 3));
+        for (col, header) in ["Data", "Descrizione", "Importo", "Saldo"]
+            .iter()
+            .enumerate()
+        {
+            range.set_value((0, col as u32), Data::String(header.to_string()));
+        }
+        range.set_value((1, 0), Data::Float(45659.0));
+        range.set_value((1, 1), Data::String("BONIFICO".to_string()));
+        range.set_value((1, 2), Data::String("1.234,56".to_string()));
+        range.set_value((1, 3), Data::Float(2234.56));
+        range.set_value((2, 0), Data::Float(45660.0));
+        range.set_value((2, 1), Data::String("POS BAR".to_string()));
+        range.set_value((2, 2), Data::Float(-12.5));
+        range.set_value((2, 3), Data::String("2.222,06".to_string()));
+        range.set_value((3, 0), Data::Float(45661.0));
+        range.set_value((3, 1), Data::String("ERRATA".to_string()));
+        range.set_value((3, 2), Data::String("n.d.".to_string()));
+
+        let parsed = process_worksheet(
+            &range,
+            &mapping,
+            &profile.decimal_separator,
+            &profile.thousands_separator,
+        )
+        .unwrap();
+        assert_eq!(parsed.transactions.len(), 2);
+        let first = &parsed.transactions[0];
+        assert_eq!(first.date, date("2025-01-02"));
+        assert!((first.value - 1234.56).abs() < 0.001);
+        assert_eq!(first.balance, Some(2234.56));
+        let second = &parsed.transactions[1];
+        assert!((second.value + 12.5).abs() < 0.001);
+        assert_eq!(second.balance, Some(2222.06));
+        assert_eq!(parsed.rejected_rows.len(), 1);
+    }
+
+    fn ledger_transaction(id: i32, day: &str, value: f64, description: &str) -> transaction::Model {
+        transaction::Model {
+            id,
+            account_id: 1,
+            category_id: None,
+            value,
+            description: description.to_string(),
+            date: date(day).and_hms_opt(0, 0, 0).unwrap(),
+            perc_to_exclude: 0.0,
+            label: String::new(),
+            external_id: None,
+            import_batch_id: None,
+        }
+    }
+
+    fn row(day: &str, value: f64, description: &str) -> TransactionData {
+        TransactionData {
+            description: description.to_string(),
+            value,
+            date: date(day),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn duplicate_index() {
+        let mut index = DuplicateIndex::default();
+        index.record(&ledger_transaction(1, "2025-01-02", -12.5, "POS BAR ROMA"));
+        index.record(&ledger_transaction(2, "2025-01-10", -40.0, "ESSELUNGA"));
+        index.external_ids.insert("FIT1".to_string());
+
+        // Same day, amount and description once punctuation and case are
+        // ignored.
+        assert!(matches!(
+            index.check(&row("2025-01-02", -12.5, "pos bar, roma")),
+            DuplicateStatus::Duplicate
+        ));
+        // The ledger has it once, a second identical row is new.
+        assert!(matches!(
+            index.check(&row("2025-01-02", -12.5, "POS BAR ROMA")),
+            DuplicateStatus::NearDuplicate(1)
+        ));
+        // Same description and amount a couple of days later.
+        assert!(matches!(
+            index.check(&row("2025-01-12", -40.0, "ESSELUNGA")),
+            DuplicateStatus::NearDuplicate(2)
+        ));
+        assert!(matches!(
+            index.check(&row("2025-01-20", -40.0, "ESSELUNGA")),
+            DuplicateStatus::Unique
+        ));
+
+        let mut known_id = row("2025-02-01", -1.0, "ANYTHING");
+        known_id.external_id = Some("FIT1".to_string());
+        assert!(matches!(index.check(&known_id), DuplicateStatus::Duplicate));
+        let mut repeated_id = row("2025-02-01", -2.0, "OTHER");
+        repeated_id.external_id = Some("FIT2".to_string());
+        assert!(matches!(index.check(&repeated_id), DuplicateStatus::Unique));
+        assert!(matches!(
+            index.check(&repeated_id),
+            DuplicateStatus::Duplicate
+        ));
+    }
+
+    #[tokio::test]
+    async fn mt940_movements_and_balances() {
+        let statement = "\
+:20:STMT1
+:60F:C250101EUR100,00
+:61:2501020102D5,00NTRFNONREF//BANK1
+:86:?20Caffè?xé bar?32ROSSI
+:61:2513400134D5,00NTRFNONREF
+:86:rejected
+:61:2501040104C10,00NTRFNONREF//BANK2
+:86:Stipendio
+:62F:C250131EUR105,00
+:86:Statement level note
+:20:STMT2
+:86:Before any movement
+:60F:C250131EUR105,00
+:62F:C250228EUR105,00
+";
+        let parsed = process_mt940(statement.as_bytes()).await.unwrap();
+
+        assert_eq!(parsed.transactions.len(), 2);
+        assert_eq!(parsed.rejected_rows.len(), 1);
+
+        let coffee = &parsed.transactions[0];
+        assert_eq!(coffee.date, date("2025-01-02"));
+        assert_eq!(coffee.value, -5.0);
+        assert_eq!(coffee.external_id.as_deref(), Some("BANK1"));
+        // `?x` is no subfield code, and it splits a multibyte character.
+        assert_eq!(coffee.description, "ROSSI - Caffè");
+
+        let salary = &parsed.transactions[1];
+        assert_eq!(salary.value, 10.0);
+        assert_eq!(salary.description, "Stipendio");
+
+        assert_eq!(parsed.balance_checks.len(), 2);
+        assert!(parsed.balance_checks.iter().all(|check| check.balanced));
+    }
 }
@@ -12,8 +12,11 @@ use serde::Serialize;
 use serde_json::from_slice;
 
 use crate::{
-    database::{account_rule, budget, category, entities::account, rule, settings, transaction},
-    routes::backup::{get_full_backup, FullBackupDTO},
+    database::{
+        account_rule, budget, category, entities::account, import_profile,
+        import_profiles::ensure_builtin_profiles, rule, settings, transaction,
+    },
+    routes::backup::{get_full_backup, import_profile_from_dto, FullBackupDTO, ImportProfileDTO},
 };
 
 #[derive(Template)]
@@ -31,6 +34,7 @@ struct RestoreSummary {
     transactions: usize,
     account_rules: usize,
     settings: usize,
+    import_profiles: usize,
 }
 
 pub async fn reset_sequence(
@@ -85,6 +89,7 @@ pub async fn restore_full_backup(
         transactions: 0,
         account_rules: 0,
         settings: 0,
+        import_profiles: 0,
     };
 
     while let Some(field) = multipart.next_field().await.unwrap() {
@@ -170,6 +175,23 @@ pub async fn restore_full_backup(
             .into_response();
     }
 
+    if let Err(err) = import_profile::Entity::delete_many().exec(&db).await {
+        eprintln!("Errore cancellando import_profiles: {:?}", err);
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Errore eliminando import_profiles",
+        )
+            .into_response();
+    }
+
+    // Before import profiles the column mapping lived in the settings.
+    let legacy_backup = backup.import_profiles.is_empty();
+    let account_names: Vec<(i32, String)> = backup
+        .accounts
+        .iter()
+        .map(|a| (a.id, a.name.clone()))
+        .collect();
+
     for a in backup.accounts {
         let _ = account::ActiveModel {
             id: Set(a.id),
@@ -202,6 +224,9 @@ pub async fn restore_full_backup(
             date: Set(t.date.naive_utc()),
             perc_to_exclude: Set(t.perc_to_exclude),
             label: Set(t.label),
+            external_id: Set(t.external_id),
+            // Import batches aren't part of the backup.
+            import_batch_id: Set(None),
         }
         .insert(&db)
         .await;
@@ -221,15 +246,31 @@ pub async fn restore_full_backup(
     }
 
     for r in backup.rules {
+        let patterns = match r.regexpr {
+            Some(regexpr) if r.patterns.is_empty() => regexpr
+                .split(',')
+                .filter(|pattern| !pattern.is_empty())
+                .map(String::from)
+                .collect(),
+            _ => r.patterns,
+        };
         let _ = rule::ActiveModel {
             id: Set(r.id),
             name: Set(r.name),
             label: Set(r.label),
             percentage: Set(r.percentage),
             category_id: Set(r.category_id),
-            regexpr: Set(r.regexpr),
+            patterns: Set(rule::Patterns(patterns)),
+            patterns_case_insensitive: Set(r.patterns_case_insensitive),
             date_start: Set(r.date_start),
             date_end: Set(r.date_end),
+            amount_min: Set(r.amount_min),
+            amount_max: Set(r.amount_max),
+            amount_exact: Set(r.amount_exact),
+            amount_tolerance: Set(r.amount_tolerance),
+            amount_sign: Set(r.amount_sign),
+            conditions: Set(r.conditions),
+            priority: Set(r.priority),
         }
         .insert(&db)
         .await;
@@ -247,14 +288,44 @@ pub async fn restore_full_backup(
         summary.account_rules += 1;
     }
 
+    for p in backup.import_profiles.into_iter() {
+        let id = p.id;
+        let mut profile = import_profile_from_dto(p);
+        profile.id = Set(id);
+        let _ = profile.insert(&db).await;
+        summary.import_profiles += 1;
+    }
+
     for settings in backup.settings {
+        let mut profile_id = settings.profile_id;
+
+        if legacy_backup {
+            let name = account_names
+                .iter()
+                .find(|(id, _)| *id == settings.account_id)
+                .map(|(_, name)| name.clone())
+                .unwrap_or_else(|| format!("Account {}", settings.account_id));
+            let profile = import_profile_from_dto(ImportProfileDTO {
+                id: 0,
+                name,
+                builtin: false,
+                format: import_profile::FORMAT_AUTO.to_string(),
+                mapping: settings.legacy_mapping,
+            });
+            if let Ok(profile) = profile.insert(&db).await {
+                profile_id = Some(profile.id);
+                summary.import_profiles += 1;
+            }
+        }
+
         let _ = settings::ActiveModel {
             id: Set(settings.id),
             account_id: Set(settings.account_id),
-            date_index: Set(settings.date_index),
-            description_index: Set(settings.description_index),
-            value_index: Set(settings.value_index),
-            starter_string: Set(settings.starter_string),
+            profile_id: Set(profile_id),
+            auto_apply_rules: Set(settings.auto_apply_rules),
+            verified_balance: Set(settings.verified_balance),
+            verified_balance_date: Set(settings.verified_balance_date),
+            rule_conflict_policy: Set(settings.rule_conflict_policy),
         }
         .insert(&db)
         .await;
@@ -270,6 +341,7 @@ pub async fn restore_full_backup(
         ("rules", "rules_id_seq"),
         ("account_rules", "account_rules_id_seq"),
         ("settings", "settings_id_seq"),
+        ("import_profiles", "import_profiles_id_seq"),
     ];
 
     for (table, seq) in sequences.iter() {
@@ -283,5 +355,9 @@ pub async fn restore_full_backup(
         }
     }
 
+    if let Err(e) = ensure_builtin_profiles(&db).await {
+        eprintln!("Errore ricreando i profili di import predefiniti: {:?}", e);
+    }
+
     (StatusCode::OK, axum::Json(summary)).into_response()
 }
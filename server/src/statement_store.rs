@@ -0,0 +1,51 @@
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use sha2::{Digest, Sha256};
+use tokio::fs;
+
+/// Original statement files are kept on disk under their SHA-256, so every
+/// import can be traced back to the bank's document and parsed again. The
+/// folder is `STATEMENT_STORE_DIR`, `statements` by default.
+fn store_dir() -> PathBuf {
+    std::env::var("STATEMENT_STORE_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("statements"))
+}
+
+fn file_path(file_hash: &str) -> anyhow::Result<PathBuf> {
+    if file_hash.len() != 64 || !file_hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!("Invalid statement hash '{}'", file_hash));
+    }
+
+    Ok(store_dir().join(&file_hash[..2]).join(file_hash))
+}
+
+/// Hash a statement file is stored under.
+pub fn hash(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Stores a statement file and returns its hash. A file already stored is
+/// left untouched, the same statement uploaded twice is kept once.
+pub async fn save(data: &[u8]) -> anyhow::Result<String> {
+    let file_hash = hash(data);
+    let path = file_path(&file_hash)?;
+
+    if !fs::try_exists(&path).await? {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).await?;
+        }
+        // Written aside and renamed, so a half written file never has the
+        // name of a complete one.
+        let partial = path.with_extension("partial");
+        fs::write(&partial, data).await?;
+        fs::rename(&partial, &path).await?;
+    }
+
+    Ok(file_hash)
+}
+
+pub async fn load(file_hash: &str) -> anyhow::Result<Vec<u8>> {
+    Ok(fs::read(file_path(file_hash)?).await?)
+}
@@ -32,6 +32,9 @@ export function renderPreview(previewData, previewList, conflictSelections) {
         clone.querySelector(".new-values .perc-new").textContent = txt.perc_to_exclude_new_value || "-";
         clone.querySelector(".new-values .category-new").textContent = txt.category_new_value || "-";
 
+        const reason = clone.querySelector(".rule-reason");
+        reason.textContent = txt.winning_rule ? `${txt.winning_rule}: ${txt.reason}` : txt.reason;
+
         const conflictsContainer = clone.querySelector(".conflicts");
         if (txt.conflicts && txt.conflicts.length > 1) {
             txt.conflicts.forEach(rule => {